@@ -0,0 +1,49 @@
+-- The JSON blob per game is split up: settings become columns of game,
+-- players and rounds get tables of their own. The old blobs wait in
+-- legacy_game until they are converted when the server starts.
+CREATE TABLE IF NOT EXISTS legacy_game (
+    id   INTEGER PRIMARY KEY REFERENCES game(id) ON DELETE CASCADE,
+    game TEXT    NOT NULL
+);
+
+INSERT INTO legacy_game (id, game) SELECT id, game FROM game;
+
+ALTER TABLE game DROP COLUMN game;
+ALTER TABLE game ADD COLUMN name         TEXT    NOT NULL DEFAULT '';
+ALTER TABLE game ADD COLUMN rules        TEXT    NOT NULL DEFAULT '"Whisthub"';
+ALTER TABLE game ADD COLUMN first_dealer INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE game ADD COLUMN end_rounds   INTEGER;
+ALTER TABLE game ADD COLUMN end_score    INTEGER;
+ALTER TABLE game ADD COLUMN finished     INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE game ADD COLUMN undone       TEXT    NOT NULL DEFAULT '[]';
+
+CREATE TABLE IF NOT EXISTS player (
+    game_id INTEGER NOT NULL REFERENCES game(id) ON DELETE CASCADE,
+    seat    INTEGER NOT NULL,
+    name    TEXT    NOT NULL,
+    PRIMARY KEY (game_id, seat)
+);
+
+CREATE TABLE IF NOT EXISTS round (
+    game_id  INTEGER NOT NULL REFERENCES game(id) ON DELETE CASCADE,
+    seq      INTEGER NOT NULL,
+    kind     TEXT    NOT NULL CHECK (kind IN ('deal', 'pass', 'correction')),
+    bid      TEXT,
+    achieved INTEGER,
+    reason   TEXT,
+    PRIMARY KEY (game_id, seq)
+);
+
+-- role is NULL for corrections, tricks is only set for several misery players
+CREATE TABLE IF NOT EXISTS round_seat (
+    game_id INTEGER NOT NULL,
+    seq     INTEGER NOT NULL,
+    seat    INTEGER NOT NULL,
+    role    TEXT    CHECK (role IN ('team', 'opp')),
+    tricks  INTEGER,
+    points  INTEGER NOT NULL,
+    PRIMARY KEY (game_id, seq, seat),
+    FOREIGN KEY (game_id, seq) REFERENCES round(game_id, seq) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS round_seat_seat_idx ON round_seat(game_id, seat);
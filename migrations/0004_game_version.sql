@@ -0,0 +1,3 @@
+-- Every save bumps the version, so that a save based on an older
+-- version of the game can be refused instead of overwriting it.
+ALTER TABLE game ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- The response to every submission that carried an idempotency key, so that
+-- a form that is submitted twice gets the same answer instead of being
+-- applied twice. Keys are forgotten after a day.
+CREATE TABLE IF NOT EXISTS idempotency_key (
+    game_id    INTEGER NOT NULL REFERENCES game(id) ON DELETE CASCADE,
+    key        TEXT    NOT NULL,
+    status     INTEGER NOT NULL,
+    headers    TEXT    NOT NULL,
+    body       TEXT    NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    PRIMARY KEY (game_id, key)
+);
@@ -0,0 +1,12 @@
+-- Long-lived tokens for scripts and bots, sent as `Authorization: Bearer`.
+-- Only a SHA-256 hash of a token is kept, the token itself is shown once.
+CREATE TABLE IF NOT EXISTS api_token (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    login_id   INTEGER NOT NULL REFERENCES login(id) ON DELETE CASCADE,
+    name       TEXT    NOT NULL,
+    hash       TEXT    NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    last_used  INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS api_token_login_idx ON api_token(login_id);
@@ -0,0 +1,3 @@
+-- A shared game has a nonce, that its signed share links are made with.
+-- A new nonce, or none, makes the links that were handed out stop working.
+ALTER TABLE game ADD COLUMN share_nonce TEXT;
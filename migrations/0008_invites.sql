@@ -0,0 +1,13 @@
+-- An account only joins a game by accepting an invite for one of its seats.
+-- There is one open invite per seat, a new one replaces it.
+CREATE TABLE IF NOT EXISTS invite (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    game_id    INTEGER NOT NULL REFERENCES game(id)  ON DELETE CASCADE,
+    login_id   INTEGER NOT NULL REFERENCES login(id) ON DELETE CASCADE,
+    alias      TEXT    NOT NULL,
+    invited_by INTEGER NOT NULL REFERENCES login(id) ON DELETE CASCADE,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    UNIQUE (game_id, alias)
+);
+
+CREATE INDEX IF NOT EXISTS invite_login_idx ON invite(login_id);
@@ -0,0 +1,9 @@
+-- A short code to join a game with, for whoever has it. A code is used only
+-- once and expires after a few minutes, a game has at most one at a time.
+CREATE TABLE IF NOT EXISTS join_code (
+    code       TEXT    PRIMARY KEY,
+    game_id    INTEGER NOT NULL REFERENCES game(id) ON DELETE CASCADE,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS join_code_game_idx ON join_code(game_id);
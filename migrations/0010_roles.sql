@@ -0,0 +1,7 @@
+-- What each player may do in a game. Whoever was linked first made the game
+-- and owns it, everybody else keeps adding rounds as before.
+ALTER TABLE plays ADD COLUMN role TEXT NOT NULL DEFAULT 'editor'
+    CHECK (role IN ('owner', 'editor', 'viewer'));
+
+UPDATE plays SET role = 'owner'
+WHERE rowid IN (SELECT MIN(rowid) FROM plays GROUP BY game_id);
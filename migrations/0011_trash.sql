@@ -0,0 +1,7 @@
+-- Leaving a game, or having it deleted by its owner, puts the seat in the trash
+-- of its player for a while, so that the game can still be brought back.
+-- Games without seats are cleaned up.
+ALTER TABLE plays ADD COLUMN trashed_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS plays_trashed_idx ON plays(trashed_at)
+    WHERE trashed_at IS NOT NULL;
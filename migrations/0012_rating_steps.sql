@@ -0,0 +1,10 @@
+-- The rating of every linked player after each game that changed it, so that
+-- a change to one game only replays the games from that game on.
+CREATE TABLE IF NOT EXISTS rating_step (
+    game_id  INTEGER NOT NULL REFERENCES game(id)  ON DELETE CASCADE,
+    login_id INTEGER NOT NULL REFERENCES login(id) ON DELETE CASCADE,
+    elo      REAL    NOT NULL,
+    PRIMARY KEY (game_id, login_id)
+);
+
+CREATE INDEX IF NOT EXISTS rating_step_login_idx ON rating_step(login_id, game_id);
@@ -0,0 +1,10 @@
+-- What each game added to the rating of a player, so that the rating steps
+-- double as the rating history of every player.
+ALTER TABLE rating_step ADD COLUMN delta REAL NOT NULL DEFAULT 0;
+
+UPDATE rating_step SET delta = elo - COALESCE(
+    (SELECT prev.elo FROM rating_step prev
+     WHERE prev.login_id = rating_step.login_id AND prev.game_id < rating_step.game_id
+     ORDER BY prev.game_id DESC LIMIT 1),
+    1000
+);
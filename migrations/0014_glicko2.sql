@@ -0,0 +1,8 @@
+-- The rating deviation and volatility of the Glicko-2 rating system, and the
+-- system the rating steps were computed with, so a switch rates everything again.
+ALTER TABLE rating_step ADD COLUMN deviation  REAL NOT NULL DEFAULT 350;
+ALTER TABLE rating_step ADD COLUMN volatility REAL NOT NULL DEFAULT 0.06;
+ALTER TABLE rating_step ADD COLUMN system     TEXT NOT NULL DEFAULT 'elo';
+
+ALTER TABLE rating ADD COLUMN deviation  REAL NOT NULL DEFAULT 350;
+ALTER TABLE rating ADD COLUMN volatility REAL NOT NULL DEFAULT 0.06;
@@ -0,0 +1,20 @@
+-- When a game was started, so that a leaderboard can rank the games of a period.
+-- Games started before this was kept have no start, they only count all time.
+ALTER TABLE game ADD COLUMN created_at INTEGER;
+
+CREATE INDEX IF NOT EXISTS game_created_idx ON game(created_at);
+
+-- The final standings of the seasons that have ended, kept as they were
+-- when the season ended, even when its games change or are deleted later.
+CREATE TABLE IF NOT EXISTS season_archive (
+    season      TEXT    PRIMARY KEY,
+    archived_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS season_standing (
+    season   TEXT    NOT NULL REFERENCES season_archive(season) ON DELETE CASCADE,
+    login_id INTEGER NOT NULL REFERENCES login(id) ON DELETE CASCADE,
+    elo      INTEGER NOT NULL,
+    games    INTEGER NOT NULL,
+    PRIMARY KEY (season, login_id)
+);
@@ -0,0 +1,4 @@
+-- Dealers chosen during a game, as a JSON list of [round, player].
+-- Choosing a dealer used to move first_dealer, which also changed
+-- the dealers of the rounds that were already played.
+ALTER TABLE game ADD COLUMN dealer_changes TEXT NOT NULL DEFAULT '[]';
@@ -2,10 +2,13 @@ use std::time::UNIX_EPOCH;
 
 use aes_gcm::aead::Aead;
 use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit};
-use base64::engine::general_purpose::STANDARD;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
 use base64::Engine as _;
 use rand::rngs::OsRng;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
 
 use crate::error::{Error, LoginErr, TokenError};
 
@@ -13,6 +16,12 @@ const ACCESS_MSG: &str = "This is a signed token for the whistbook website";
 const REFRESH_MSG: &str = "This is a refresh token for the whistbook website";
 const TOKEN_HOURS: u64 = 24;
 const REFRESH_TOKEN_DAYS: u64 = 60;
+const API_TOKEN_PREFIX: &str = "wb_";
+/// Join codes are typed over, so they leave out characters that look alike
+const JOIN_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const JOIN_CODE_LENGTH: usize = 6;
+
+type HmacSha256 = Hmac<Sha256>;
 
 #[derive(Serialize, Deserialize)]
 pub struct Token {
@@ -111,6 +120,57 @@ pub fn verify_refresh_token(token: &str) -> Result<Token, Error> {
     Ok(token)
 }
 
+/// Creates a new API token, together with the hash under which it is stored
+pub fn create_api_token() -> (String, String) {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let token = format!("{API_TOKEN_PREFIX}{}", URL_SAFE_NO_PAD.encode(secret));
+    let hash = hash_api_token(&token);
+    (token, hash)
+}
+
+/// The hash an API token is stored and looked up by
+///
+/// API tokens are long and random, so a fast hash is enough to keep them safe.
+pub fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// A new random nonce to share a game with
+pub fn create_share_nonce() -> String {
+    let mut nonce = [0u8; 16];
+    OsRng.fill_bytes(&mut nonce);
+    URL_SAFE_NO_PAD.encode(nonce)
+}
+
+/// Signs the share link of a game, which holds for as long as the game keeps its nonce
+pub fn sign_share_link(game_id: &str, nonce: &str) -> Result<String, Error> {
+    let mac = share_mac(game_id, nonce)?;
+    Ok(URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+}
+
+pub fn verify_share_link(game_id: &str, nonce: &str, signature: &str) -> bool {
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    share_mac(game_id, nonce).is_ok_and(|mac| mac.verify_slice(&signature).is_ok())
+}
+
+fn share_mac(game_id: &str, nonce: &str) -> Result<HmacSha256, Error> {
+    let key: Vec<u8> = crate::config_bytes("TOKEN_KEY")?;
+    let key = STANDARD.decode(key).map_err(Error::EnvVarDecodeError)?;
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(&key).map_err(|_| Error::EncryptError)?;
+    mac.update(format!("share:{game_id}:{nonce}").as_bytes());
+    Ok(mac)
+}
+
+/// A new short code to join a game with
+pub fn create_join_code() -> String {
+    (0..JOIN_CODE_LENGTH)
+        .map(|_| JOIN_CODE_CHARS[(OsRng.next_u32() as usize) % JOIN_CODE_CHARS.len()] as char)
+        .collect()
+}
+
 pub fn check_email(email: &str) -> bool {
     // Split the string by the '@' symbol
     let parts: Vec<&str> = email.split('@').collect();
@@ -1,8 +1,9 @@
-/// Retroactive ELO rating seeder.
+/// Retroactive rating seeder.
 ///
-/// Reads all games from SQLite, computes ELO ratings from scratch, and writes
-/// the results to the `rating` table. Run this once after deployment to seed
-/// historical data, or after retroactively linking players to old games.
+/// Reads all games from SQLite, computes the ratings from scratch with the
+/// configured rating system, and writes the results to the `rating` table.
+/// Run this once after deployment to seed historical data, or after
+/// retroactively linking players to old games.
 ///
 /// Usage:
 ///   cargo run --bin recalculate_ratings
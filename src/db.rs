@@ -1,23 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::error::{Error, LoginErr};
-use crate::template::{IdGame, LinkedPlayer};
-use crate::whist::{Game, Players};
+use crate::rating::Rating;
+use crate::scoring::RuleSet;
+use crate::template::{ApiToken, IdGame, Invite, LinkedPlayer, RatingChange, Role, TrashedGame};
+use crate::whist::{Bid, Deal, EndCondition, Game, Players, Points, Round, Team};
 use crate::{auth, Db};
 
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use password_hash::{rand_core::OsRng, SaltString};
-use sqlx::sqlite::SqliteConnectOptions;
-use sqlx::{Row, SqlitePool};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteRow};
+use sqlx::{Row, SqliteConnection, SqliteExecutor, SqlitePool};
 
 pub async fn create_pool() -> Result<SqlitePool, Error> {
     let path = crate::config("DB_PATH")?;
     let opts = SqliteConnectOptions::new()
-        .filename(&path)
+        .filename(path)
         .create_if_missing(true)
         .foreign_keys(true);
     let pool = SqlitePool::connect_with(opts).await?;
     sqlx::migrate!("./migrations").run(&pool).await?;
+    convert_legacy_games(&pool).await?;
     Ok(pool)
 }
 
@@ -82,91 +85,568 @@ pub async fn get_user_id(db: Db, email: String) -> Result<String, Error> {
     Ok(id.to_string())
 }
 
+/// Starts a game, owned by the user with this email as its first player.
+///
+/// The game has a player from the start, so that it is never taken for an
+/// orphan by [`purge_trash`].
+/// Starts a game owned by `owner`, who plays the first player, and invites
+/// the accounts with the given login ids for the seats of the other players.
+///
+/// When one of the invites fails, the game is not started either.
 pub async fn start_game<P: Into<Players>>(
     db: Db,
+    owner: String,
     name: String,
     players: P,
+    rules: RuleSet,
+    invites: Vec<(String, String)>,
 ) -> Result<(String, Game), Error> {
-    let game = Game::new(name, players);
-    let json = serde_json::to_string(&game).unwrap();
+    let mut game = Game::new(name, players, rules);
 
-    let result = sqlx::query("INSERT INTO game (game) VALUES (?)")
-        .bind(&json)
-        .execute(&**db)
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+    let game_id = sqlx::query("INSERT INTO game (created_at) VALUES (strftime('%s', 'now'))")
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    write_settings(&mut tx, game_id, &mut game).await?;
+    write_players(&mut tx, game_id, &game.players).await?;
+    sqlx::query(
+        "INSERT INTO plays (login_id, game_id, alias, role)
+         SELECT id, ?, ?, 'owner' FROM login WHERE email = ?",
+    )
+    .bind(game_id)
+    .bind(&game.players[0])
+    .bind(&owner)
+    .execute(&mut *tx)
+    .await?;
+    for (user_id, alias) in invites {
+        write_invite(&mut tx, game_id, &owner, &user_id, &alias).await?;
+    }
+    tx.commit().await?;
+
+    Ok((game_id.to_string(), game))
+}
+
+/// Saves the settings of a game, and its rounds from round `from` on.
+///
+/// Rounds before `from` are left as they are, so adding a round
+/// to a game only appends that round.
+///
+/// Fails with [`Error::Conflict`] when the game was saved since it was loaded,
+/// and bumps the version of the game otherwise. Viewers cannot save a game,
+/// the role of whoever saved it is returned.
+pub async fn save_rounds(
+    db: Db,
+    owner: String,
+    id: String,
+    game: &mut Game,
+    from: usize,
+) -> Result<Role, Error> {
+    let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
+
+    // take the write lock right away, so that saves made at the
+    // same time wait for each other instead of failing as busy
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+    let role = require_role(&mut *tx, game_id, &owner, Role::Editor).await?;
+    write_settings(&mut tx, game_id, game).await?;
+    write_rounds(&mut tx, game_id, game, from).await?;
+    tx.commit().await?;
+
+    Ok(role)
+}
+
+pub async fn get_game(db: Db, owner: String, id: String) -> Result<Game, Error> {
+    let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
+
+    if !plays_in(&**db, game_id, &owner).await? {
+        return Err(Error::NoGameError);
+    }
+
+    load_game(&db, game_id).await
+}
+
+pub async fn get_game_by_id(db: Db, owner: String, id: String) -> Result<Game, Error> {
+    get_game(db, owner, id).await
+}
+
+/// The nonce of the share link of a game, when it is shared
+pub async fn get_share_nonce(db: Db, owner: String, id: String) -> Result<Option<String>, Error> {
+    let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
+
+    require_role(&**db, game_id, &owner, Role::Owner).await?;
+
+    let nonce = sqlx::query_scalar("SELECT share_nonce FROM game WHERE id = ?")
+        .bind(game_id)
+        .fetch_one(&**db)
         .await?;
+    Ok(nonce)
+}
+
+/// Shares a game under a new nonce, or stops sharing it with `None`.
+/// Either way, the links that were handed out before stop working.
+pub async fn set_share_nonce(
+    db: Db,
+    owner: String,
+    id: String,
+    nonce: Option<String>,
+) -> Result<(), Error> {
+    let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
+
+    require_role(&**db, game_id, &owner, Role::Owner).await?;
 
-    Ok((result.last_insert_rowid().to_string(), game))
+    sqlx::query("UPDATE game SET share_nonce = ? WHERE id = ?")
+        .bind(nonce)
+        .bind(game_id)
+        .execute(&**db)
+        .await?;
+    Ok(())
 }
 
-pub async fn save_game(db: Db, owner: String, id: String, game: Game) -> Result<(), Error> {
+/// A shared game, for anybody with a correctly signed link to it
+pub async fn get_shared_game(db: Db, id: String, signature: &str) -> Result<Game, Error> {
     let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
-    let json = serde_json::to_string(&game).unwrap();
 
-    sqlx::query(
-        "UPDATE game SET game = ?
-         WHERE id = ?
-           AND EXISTS (
-               SELECT 1 FROM plays p
-               JOIN login l ON l.id = p.login_id
-               WHERE p.game_id = game.id AND l.email = ?
-           )",
-    )
-    .bind(&json)
-    .bind(game_id)
+    let nonce: Option<Option<String>> =
+        sqlx::query_scalar("SELECT share_nonce FROM game WHERE id = ?")
+            .bind(game_id)
+            .fetch_optional(&**db)
+            .await?;
+
+    match nonce.flatten() {
+        Some(nonce) if auth::verify_share_link(&id, &nonce, signature) => {
+            load_game(&db, game_id).await
+        }
+        _ => Err(Error::NoGameError),
+    }
+}
+
+pub async fn get_games_with_ids(db: Db, owner: String) -> Result<Vec<IdGame>, Error> {
+    let ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT p.game_id FROM plays p
+         JOIN login l ON l.id = p.login_id
+         WHERE l.email = ? AND p.trashed_at IS NULL",
+    )
     .bind(&owner)
-    .execute(&**db)
+    .fetch_all(&**db)
     .await?;
 
-    Ok(())
+    let mut games = Vec::with_capacity(ids.len());
+    for id in ids {
+        games.push(IdGame {
+            id: id.to_string(),
+            game: load_game(&db, id).await?,
+        });
+    }
+    Ok(games)
 }
 
-pub async fn get_game(db: Db, owner: String, id: String) -> Result<Game, Error> {
+/// Whether the user with this email plays in the game
+async fn plays_in<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    game_id: i64,
+    owner: &str,
+) -> Result<bool, Error> {
+    Ok(role_in(executor, game_id, owner).await?.is_some())
+}
+
+/// The role of the user with this email in the game, if it plays in it.
+/// A game in the trash of the user is not played in.
+async fn role_in<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    game_id: i64,
+    owner: &str,
+) -> Result<Option<Role>, Error> {
+    let role: Option<String> = sqlx::query_scalar(
+        "SELECT p.role FROM plays p
+         JOIN login l ON l.id = p.login_id
+         WHERE p.game_id = ? AND l.email = ? AND p.trashed_at IS NULL",
+    )
+    .bind(game_id)
+    .bind(owner)
+    .fetch_optional(executor)
+    .await?;
+
+    role.map(|role| role.parse()).transpose()
+}
+
+/// Fails unless the user with this email has at least the `needed` role in the game
+async fn require_role<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    game_id: i64,
+    owner: &str,
+    needed: Role,
+) -> Result<Role, Error> {
+    match role_in(executor, game_id, owner).await? {
+        None => Err(Error::NoGameError),
+        Some(role) if role < needed => Err(Error::NotAllowed(needed)),
+        Some(role) => Ok(role),
+    }
+}
+
+/// The role of the user with this email in the game
+pub async fn get_role(db: Db, owner: String, id: String) -> Result<Role, Error> {
     let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
 
+    role_in(&**db, game_id, &owner)
+        .await?
+        .ok_or(Error::NoGameError)
+}
+
+/// Gives the account linked to a player of the game another role.
+/// Only owners decide on roles, and the last owner cannot step down.
+pub async fn set_role(
+    db: Db,
+    owner: String,
+    game_id: String,
+    alias: String,
+    role: Role,
+) -> Result<(), Error> {
+    let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
+
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+    require_role(&mut *tx, gid, &owner, Role::Owner).await?;
+
+    let current: String =
+        sqlx::query_scalar("SELECT role FROM plays WHERE game_id = ? AND alias = ?")
+            .bind(gid)
+            .bind(&alias)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::UnknownPlayer)?;
+
+    if current.parse::<Role>()?.is_owner() && !role.is_owner() {
+        let owners: i64 =
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM plays
+                 WHERE game_id = ? AND role = 'owner' AND trashed_at IS NULL",
+            )
+            .bind(gid)
+            .fetch_one(&mut *tx)
+            .await?;
+        if owners < 2 {
+            return Err(Error::LastOwner);
+        }
+    }
+
+    sqlx::query("UPDATE plays SET role = ? WHERE game_id = ? AND alias = ?")
+        .bind(role.as_str())
+        .bind(gid)
+        .bind(&alias)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// One player in a stored round
+struct SeatRow {
+    seat: usize,
+    role: Option<String>,
+    tricks: Option<i16>,
+    points: i16,
+}
+
+/// Puts a game back together from its settings, players and rounds
+async fn load_game(pool: &SqlitePool, game_id: i64) -> Result<Game, Error> {
+    // one transaction, so that all queries see the same rounds
+    let mut tx = pool.begin().await?;
+
     let row = sqlx::query(
-        "SELECT g.game FROM game g
-         JOIN plays p ON p.game_id = g.id
-         JOIN login l ON l.id = p.login_id
-         WHERE g.id = ? AND l.email = ?",
+        "SELECT name, rules, first_dealer, dealer_changes, end_rounds, end_score, finished,
+                undone, version
+         FROM game WHERE id = ?",
     )
     .bind(game_id)
-    .bind(&owner)
-    .fetch_optional(&**db)
+    .fetch_optional(&mut *tx)
     .await?
     .ok_or(Error::NoGameError)?;
 
-    let json: String = row.try_get("game")?;
-    serde_json::from_str(&json).map_err(|_| Error::NoGameError)
+    let names: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM player WHERE game_id = ? ORDER BY seat")
+            .bind(game_id)
+            .fetch_all(&mut *tx)
+            .await?;
+    let players: Players = names.as_slice().into();
+
+    let mut seats: HashMap<i64, Vec<SeatRow>> = HashMap::new();
+    let seat_rows = sqlx::query(
+        "SELECT seq, seat, role, tricks, points FROM round_seat
+         WHERE game_id = ? ORDER BY seq, seat",
+    )
+    .bind(game_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    for r in seat_rows {
+        let seat: i64 = r.try_get("seat")?;
+        seats.entry(r.try_get("seq")?).or_default().push(SeatRow {
+            seat: seat as usize,
+            role: r.try_get("role")?,
+            tricks: r.try_get("tricks")?,
+            points: r.try_get("points")?,
+        });
+    }
+
+    let round_rows = sqlx::query(
+        "SELECT seq, kind, bid, achieved, reason FROM round
+         WHERE game_id = ? ORDER BY seq",
+    )
+    .bind(game_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    let mut deals = Vec::with_capacity(round_rows.len());
+    for r in round_rows {
+        let seq: i64 = r.try_get("seq")?;
+        let seats = seats.remove(&seq).unwrap_or_default();
+        let round = match r.try_get::<String, _>("kind")?.as_str() {
+            "pass" => Round::AllPass,
+            "correction" => {
+                let mut points = Points(vec![0; players.len()]);
+                for s in seats {
+                    points[s.seat] = s.points;
+                }
+                Round::Correction {
+                    reason: r.try_get::<Option<String>, _>("reason")?.unwrap_or_default(),
+                    points,
+                }
+            }
+            "deal" => {
+                let bid: Bid = r
+                    .try_get::<String, _>("bid")?
+                    .parse()
+                    .map_err(|_| Error::NoGameError)?;
+                let achieved = r.try_get::<Option<i16>, _>("achieved")?.unwrap_or_default();
+                Round::Deal(Deal {
+                    team: read_team(seats)?,
+                    bid,
+                    achieved,
+                })
+            }
+            _ => return Err(Error::NoGameError),
+        };
+        deals.push(round);
+    }
+
+    let first_dealer: i64 = row.try_get("first_dealer")?;
+    let end = match (
+        row.try_get::<Option<i64>, _>("end_rounds")?,
+        row.try_get::<Option<i16>, _>("end_score")?,
+    ) {
+        (Some(rounds), _) => Some(EndCondition::Rounds(rounds as usize)),
+        (None, Some(score)) => Some(EndCondition::Score(score)),
+        (None, None) => None,
+    };
+    let rules: String = row.try_get("rules")?;
+    let dealer_changes: String = row.try_get("dealer_changes")?;
+    let undone: String = row.try_get("undone")?;
+
+    let mut game = Game {
+        name: row.try_get("name")?,
+        players,
+        scores: vec![],
+        deals,
+        rules: serde_json::from_str(&rules).map_err(|_| Error::NoGameError)?,
+        first_dealer: first_dealer as usize,
+        dealer_changes: serde_json::from_str(&dealer_changes).map_err(|_| Error::NoGameError)?,
+        undone: serde_json::from_str(&undone).map_err(|_| Error::NoGameError)?,
+        end,
+        finished: row.try_get("finished")?,
+        version: row.try_get("version")?,
+    };
+    game.recompute_scores()?;
+    tx.commit().await?;
+
+    Ok(game)
 }
 
-pub async fn get_game_by_id(db: Db, owner: String, id: String) -> Result<Game, Error> {
-    get_game(db, owner, id).await
+/// Rebuilds the team of a deal from the roles of its players
+fn read_team(seats: Vec<SeatRow>) -> Result<Team, Error> {
+    let (team, opps): (Vec<_>, Vec<_>) = seats
+        .into_iter()
+        .partition(|s| s.role.as_deref() == Some("team"));
+    let opps: Vec<usize> = opps.iter().map(|s| s.seat).collect();
+
+    if team.iter().any(|s| s.tricks.is_some()) {
+        let players = team
+            .iter()
+            .map(|s| (s.seat, s.tricks.unwrap_or_default()))
+            .collect();
+        return Ok(Team::Misery(players, opps));
+    }
+
+    match (team.as_slice(), opps.as_slice()) {
+        ([player], &[o1, o2, o3]) => Ok(Team::Solo(player.seat, (o1, o2, o3))),
+        ([p1, p2], &[o1, o2]) => Ok(Team::Duo((p1.seat, p2.seat), (o1, o2))),
+        _ => Err(Error::NoGameError),
+    }
 }
 
-pub async fn get_games_with_ids(db: Db, owner: String) -> Result<Vec<IdGame>, Error> {
-    let rows = sqlx::query(
-        "SELECT g.id, g.game FROM game g
-         JOIN plays p ON p.game_id = g.id
-         JOIN login l ON l.id = p.login_id
-         WHERE l.email = ?",
+/// Writes the settings of a game, as long as nobody saved it since it was loaded
+async fn write_settings(
+    conn: &mut SqliteConnection,
+    game_id: i64,
+    game: &mut Game,
+) -> Result<(), Error> {
+    let (end_rounds, end_score) = match game.end {
+        None => (None, None),
+        Some(EndCondition::Rounds(rounds)) => (Some(rounds as i64), None),
+        Some(EndCondition::Score(score)) => (None, Some(score)),
+    };
+
+    let result = sqlx::query(
+        "UPDATE game
+         SET name = ?, rules = ?, first_dealer = ?, dealer_changes = ?, end_rounds = ?,
+             end_score = ?, finished = ?, undone = ?, version = version + 1
+         WHERE id = ? AND version = ?",
     )
-    .bind(&owner)
-    .fetch_all(&**db)
+    .bind(&game.name)
+    .bind(serde_json::to_string(&game.rules).unwrap())
+    .bind(game.first_dealer as i64)
+    .bind(serde_json::to_string(&game.dealer_changes).unwrap())
+    .bind(end_rounds)
+    .bind(end_score)
+    .bind(game.finished)
+    .bind(serde_json::to_string(&game.undone).unwrap())
+    .bind(game_id)
+    .bind(game.version)
+    .execute(&mut *conn)
     .await?;
 
-    rows.into_iter()
-        .map(|r| {
-            let id: i64 = r.try_get("id")?;
-            let json: String = r.try_get("game")?;
-            let game: Game = serde_json::from_str(&json).map_err(|_| {
-                sqlx::Error::Decode("failed to deserialize game JSON".into())
-            })?;
-            Ok(IdGame {
-                id: id.to_string(),
-                game,
-            })
-        })
-        .collect()
+    if result.rows_affected() == 0 {
+        return Err(Error::Conflict);
+    }
+    game.version += 1;
+
+    Ok(())
+}
+
+async fn write_players(
+    conn: &mut SqliteConnection,
+    game_id: i64,
+    players: &Players,
+) -> Result<(), Error> {
+    for (seat, name) in players.into_iter().enumerate() {
+        sqlx::query("INSERT INTO player (game_id, seat, name) VALUES (?, ?, ?)")
+            .bind(game_id)
+            .bind(seat as i64)
+            .bind(name)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Replaces the stored rounds from round `from` on with those of the game
+async fn write_rounds(
+    conn: &mut SqliteConnection,
+    game_id: i64,
+    game: &Game,
+    from: usize,
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM round WHERE game_id = ? AND seq >= ?")
+        .bind(game_id)
+        .bind(from as i64)
+        .execute(&mut *conn)
+        .await?;
+
+    for (seq, round) in game.deals.iter().enumerate().skip(from) {
+        let (kind, bid, achieved, reason) = match round {
+            Round::Deal(deal) => ("deal", Some(deal.bid.to_string()), Some(deal.achieved), None),
+            Round::AllPass => ("pass", None, None, None),
+            Round::Correction { reason, .. } => ("correction", None, None, Some(reason)),
+        };
+
+        sqlx::query(
+            "INSERT INTO round (game_id, seq, kind, bid, achieved, reason)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(game_id)
+        .bind(seq as i64)
+        .bind(kind)
+        .bind(bid)
+        .bind(achieved)
+        .bind(reason)
+        .execute(&mut *conn)
+        .await?;
+
+        // (seat, role, tricks) of everybody that took part
+        let seats: Vec<(usize, Option<&str>, Option<i16>)> = match round {
+            Round::Deal(deal) => match &deal.team {
+                Team::Solo(player, (o1, o2, o3)) => vec![
+                    (*player, Some("team"), None),
+                    (*o1, Some("opp"), None),
+                    (*o2, Some("opp"), None),
+                    (*o3, Some("opp"), None),
+                ],
+                Team::Duo((p1, p2), (o1, o2)) => vec![
+                    (*p1, Some("team"), None),
+                    (*p2, Some("team"), None),
+                    (*o1, Some("opp"), None),
+                    (*o2, Some("opp"), None),
+                ],
+                Team::Misery(players, opps) => players
+                    .iter()
+                    .map(|&(player, tricks)| (player, Some("team"), Some(tricks)))
+                    .chain(opps.iter().map(|&opp| (opp, Some("opp"), None)))
+                    .collect(),
+            },
+            Round::AllPass => vec![],
+            Round::Correction { .. } => (0..game.players.len()).map(|s| (s, None, None)).collect(),
+        };
+
+        let points = game.round_points(&seq);
+        for (seat, role, tricks) in seats {
+            sqlx::query(
+                "INSERT INTO round_seat (game_id, seq, seat, role, tricks, points)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(game_id)
+            .bind(seq as i64)
+            .bind(seat as i64)
+            .bind(role)
+            .bind(tricks)
+            .bind(points[seat])
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves games that were stored as one JSON blob into their own tables.
+///
+/// A game that cannot be converted stops the start: its row in `game` has no
+/// players, and every view of it would fail. Fix or remove it in `legacy_game`.
+async fn convert_legacy_games(pool: &SqlitePool) -> Result<(), Error> {
+    let rows = sqlx::query("SELECT id, game FROM legacy_game")
+        .fetch_all(pool)
+        .await?;
+
+    for row in rows {
+        let game_id: i64 = row.try_get("id")?;
+        let json: String = row.try_get("game")?;
+        let invalid = |reason: String| Error::LegacyGame(game_id, reason);
+        let mut game = serde_json::from_str::<Game>(&json).map_err(|e| invalid(e.to_string()))?;
+        if game.players.len() < 4 {
+            return Err(invalid("it has less than 4 players".into()));
+        }
+        if let Err(e) = game.recompute_scores() {
+            return Err(invalid(e.to_string()));
+        }
+
+        let mut tx = pool.begin().await?;
+        write_settings(&mut tx, game_id, &mut game).await?;
+        write_players(&mut tx, game_id, &game.players).await?;
+        write_rounds(&mut tx, game_id, &game, 0).await?;
+        sqlx::query("DELETE FROM legacy_game WHERE id = ?")
+            .bind(game_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
 }
 
 pub async fn num_players(db: Db, game_id: String) -> Result<usize, Error> {
@@ -178,116 +658,880 @@ pub async fn num_players(db: Db, game_id: String) -> Result<usize, Error> {
     Ok(count as usize)
 }
 
-pub async fn delete_game_by_id(db: Db, owner: String, id: String) -> Result<(), Error> {
+/// Puts a game in the trash. An owner deletes the game, which puts it in the
+/// trash of all its players, everybody else only leaves it.
+pub async fn trash_game(db: Db, owner: String, id: String) -> Result<(), Error> {
     let game_id: i64 = id.parse().map_err(|_| Error::NoGameError)?;
 
-    sqlx::query(
-        "DELETE FROM game WHERE id = ?
-         AND EXISTS (
-             SELECT 1 FROM plays p
-             JOIN login l ON l.id = p.login_id
-             WHERE p.game_id = game.id AND l.email = ?
-         )",
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+    let role = role_in(&mut *tx, game_id, &owner)
+        .await?
+        .ok_or(Error::NoGameError)?;
+
+    if role.is_owner() {
+        sqlx::query(
+            "UPDATE plays SET trashed_at = strftime('%s', 'now')
+             WHERE game_id = ? AND trashed_at IS NULL",
+        )
+        .bind(game_id)
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        sqlx::query(
+            "UPDATE plays SET trashed_at = strftime('%s', 'now')
+             WHERE game_id = ? AND login_id = (SELECT id FROM login WHERE email = ?)",
+        )
+        .bind(game_id)
+        .bind(&owner)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// The games in the trash of a user that can still be restored, the latest first
+pub async fn get_trash(db: Db, email: String, window: i64) -> Result<Vec<TrashedGame>, Error> {
+    let rows = sqlx::query(
+        "SELECT p.game_id, g.name, p.trashed_at + ? - strftime('%s', 'now') AS left
+         FROM plays p
+         JOIN login l ON l.id = p.login_id
+         JOIN game g ON g.id = p.game_id
+         WHERE l.email = ? AND p.trashed_at >= strftime('%s', 'now') - ?
+         ORDER BY p.trashed_at DESC",
+    )
+    .bind(window)
+    .bind(&email)
+    .bind(window)
+    .fetch_all(&**db)
+    .await?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(TrashedGame {
+                id: r.try_get::<i64, _>("game_id")?.to_string(),
+                name: r.try_get("name")?,
+                seconds_left: r.try_get("left")?,
+            })
+        })
+        .collect()
+}
+
+/// Takes a game out of the trash of a user, while that is still possible.
+///
+/// When an owner deleted the game, its owners are in the trash as well:
+/// whoever restores it first then owns it, so that it keeps an owner.
+pub async fn restore_game(db: Db, email: String, id: String, window: i64) -> Result<(), Error> {
+    let game_id: i64 = id.parse().map_err(|_| Error::NotInTrash)?;
+
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+    let restored = sqlx::query(
+        "UPDATE plays SET trashed_at = NULL
+         WHERE game_id = ? AND login_id = (SELECT id FROM login WHERE email = ?)
+         AND trashed_at >= strftime('%s', 'now') - ?",
     )
     .bind(game_id)
-    .bind(&owner)
-    .execute(&**db)
+    .bind(&email)
+    .bind(window)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if restored == 0 {
+        return Err(Error::NotInTrash);
+    }
+
+    let owners: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM plays
+         WHERE game_id = ? AND role = ? AND trashed_at IS NULL",
+    )
+    .bind(game_id)
+    .bind(Role::Owner.as_str())
+    .fetch_one(&mut *tx)
     .await?;
+    if owners == 0 {
+        sqlx::query(
+            "UPDATE plays SET role = ?
+             WHERE game_id = ? AND login_id = (SELECT id FROM login WHERE email = ?)",
+        )
+        .bind(Role::Owner.as_str())
+        .bind(game_id)
+        .bind(&email)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
 
     Ok(())
 }
 
-pub async fn add_player(
+/// Empties the trash of everything that has been in it for longer than `window`
+/// seconds, and deletes the games that no player is left in.
+///
+/// Returns how many games were deleted.
+pub async fn purge_trash(db: Db, window: i64) -> Result<u64, Error> {
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+
+    sqlx::query("DELETE FROM plays WHERE trashed_at < strftime('%s', 'now') - ?")
+        .bind(window)
+        .execute(&mut *tx)
+        .await?;
+    let purged = sqlx::query(
+        "DELETE FROM game
+         WHERE NOT EXISTS (SELECT 1 FROM plays p WHERE p.game_id = game.id)",
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+
+    Ok(purged)
+}
+
+/// Invites the account with a login id to take a seat in a game.
+/// It only joins the game when it accepts.
+pub async fn invite_player(
     db: Db,
+    owner: String,
     game_id: String,
     user_id: String,
-    user_alias: String,
+    alias: String,
 ) -> Result<(), Error> {
     let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
-    let uid: i64 = user_id.parse().map_err(|_| Error::NoGameError)?;
 
-    sqlx::query("INSERT OR IGNORE INTO plays (login_id, game_id, alias) VALUES (?, ?, ?)")
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+    require_role(&mut *tx, gid, &owner, Role::Owner).await?;
+    write_invite(&mut tx, gid, &owner, &user_id, &alias).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Invites the account with login id `user_id` for the seat of `alias`
+async fn write_invite(
+    conn: &mut SqliteConnection,
+    gid: i64,
+    owner: &str,
+    user_id: &str,
+    alias: &str,
+) -> Result<(), Error> {
+    let uid: i64 = user_id.trim().parse().map_err(|_| Error::UnknownUser)?;
+
+    let seat: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM player WHERE game_id = ? AND name = ?")
+            .bind(gid)
+            .bind(alias)
+            .fetch_one(&mut *conn)
+            .await?;
+    if seat == 0 {
+        return Err(Error::UnknownPlayer);
+    }
+
+    let user: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM login WHERE id = ?")
         .bind(uid)
+        .fetch_one(&mut *conn)
+        .await?;
+    if user == 0 {
+        return Err(Error::UnknownUser);
+    }
+
+    check_seat(conn, gid, uid, alias).await?;
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO invite (game_id, login_id, alias, invited_by)
+         SELECT ?, ?, ?, id FROM login WHERE email = ?",
+    )
+    .bind(gid)
+    .bind(uid)
+    .bind(alias)
+    .bind(owner)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Checks that a seat is still free, and that the account does not have a seat already
+async fn check_seat(
+    conn: &mut SqliteConnection,
+    game_id: i64,
+    login_id: i64,
+    alias: &str,
+) -> Result<(), Error> {
+    let taken: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM plays WHERE game_id = ? AND alias = ?")
+            .bind(game_id)
+            .bind(alias)
+            .fetch_one(&mut *conn)
+            .await?;
+    if taken > 0 {
+        return Err(Error::SeatTaken(alias.to_string()));
+    }
+
+    let playing: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM plays WHERE game_id = ? AND login_id = ?")
+            .bind(game_id)
+            .bind(login_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    if playing > 0 {
+        return Err(Error::AlreadyPlaying);
+    }
+
+    Ok(())
+}
+
+/// The open invites of a user, the newest first
+pub async fn get_invites(db: Db, email: String) -> Result<Vec<Invite>, Error> {
+    let rows = sqlx::query(
+        "SELECT i.id, i.game_id, g.name, i.alias, b.email AS invited_by FROM invite i
+         JOIN login l ON l.id = i.login_id
+         JOIN login b ON b.id = i.invited_by
+         JOIN game g ON g.id = i.game_id
+         WHERE l.email = ?
+         ORDER BY i.id DESC",
+    )
+    .bind(&email)
+    .fetch_all(&**db)
+    .await?;
+
+    rows.into_iter()
+        .map(|r| {
+            let game_id: i64 = r.try_get("game_id")?;
+            Ok(Invite {
+                id: r.try_get("id")?,
+                game_id: game_id.to_string(),
+                game_name: r.try_get("name")?,
+                alias: r.try_get("alias")?,
+                invited_by: r.try_get("invited_by")?,
+            })
+        })
+        .collect()
+}
+
+pub async fn count_invites(db: Db, email: String) -> Result<i64, Error> {
+    let count = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM invite i
+         JOIN login l ON l.id = i.login_id
+         WHERE l.email = ?",
+    )
+    .bind(&email)
+    .fetch_one(&**db)
+    .await?;
+    Ok(count)
+}
+
+/// The players of a game that have an open invite
+pub async fn get_invited_aliases(db: Db, game_id: String) -> Result<Vec<String>, Error> {
+    let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
+
+    let aliases = sqlx::query_scalar("SELECT alias FROM invite WHERE game_id = ?")
         .bind(gid)
-        .bind(&user_alias)
-        .execute(&**db)
+        .fetch_all(&**db)
+        .await?;
+    Ok(aliases)
+}
+
+/// Takes the seat of an invite, which is the only way into a game for somebody else.
+/// Returns the id of the game.
+pub async fn accept_invite(db: Db, email: String, invite_id: i64) -> Result<String, Error> {
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+
+    let row = sqlx::query(
+        "SELECT i.game_id, i.login_id, i.alias FROM invite i
+         JOIN login l ON l.id = i.login_id
+         WHERE i.id = ? AND l.email = ?",
+    )
+    .bind(invite_id)
+    .bind(&email)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NoInvite)?;
+    let game_id: i64 = row.try_get("game_id")?;
+    let login_id: i64 = row.try_get("login_id")?;
+    let alias: String = row.try_get("alias")?;
+
+    check_seat(&mut tx, game_id, login_id, &alias).await?;
+
+    sqlx::query("INSERT INTO plays (login_id, game_id, alias) VALUES (?, ?, ?)")
+        .bind(login_id)
+        .bind(game_id)
+        .bind(&alias)
+        .execute(&mut *tx)
+        .await?;
+    // the seat is taken, and this account has no other seat to take
+    sqlx::query("DELETE FROM invite WHERE game_id = ? AND (login_id = ? OR alias = ?)")
+        .bind(game_id)
+        .bind(login_id)
+        .bind(&alias)
+        .execute(&mut *tx)
         .await?;
+    tx.commit().await?;
+
+    Ok(game_id.to_string())
+}
+
+/// Throws an invite away
+pub async fn decline_invite(db: Db, email: String, invite_id: i64) -> Result<(), Error> {
+    let deleted = sqlx::query(
+        "DELETE FROM invite
+         WHERE id = ? AND login_id = (SELECT id FROM login WHERE email = ?)",
+    )
+    .bind(invite_id)
+    .bind(&email)
+    .execute(&**db)
+    .await?
+    .rows_affected();
 
+    if deleted == 0 {
+        return Err(Error::NoInvite);
+    }
     Ok(())
 }
 
-pub async fn remove_player(db: Db, game_id: String, user_id: String) -> Result<(), Error> {
+/// Replaces the join code of a game by a new one, that works for `lifetime` seconds
+pub async fn set_join_code(
+    db: Db,
+    owner: String,
+    game_id: String,
+    code: &str,
+    lifetime: i64,
+) -> Result<(), Error> {
     let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
-    let uid: i64 = user_id.parse().map_err(|_| Error::NoGameError)?;
 
-    sqlx::query("DELETE FROM plays WHERE game_id = ? AND login_id = ?")
+    let mut tx = (**db).begin().await?;
+    require_role(&mut *tx, gid, &owner, Role::Owner).await?;
+
+    sqlx::query("DELETE FROM join_code WHERE game_id = ? OR expires_at < strftime('%s', 'now')")
         .bind(gid)
-        .bind(uid)
-        .execute(&**db)
+        .execute(&mut *tx)
         .await?;
+    sqlx::query(
+        "INSERT INTO join_code (code, game_id, expires_at)
+         VALUES (?, ?, strftime('%s', 'now') + ?)",
+    )
+    .bind(code)
+    .bind(gid)
+    .bind(lifetime)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
 
     Ok(())
 }
 
-/// Returns all games ordered by game ID, each paired with their linked plays (login_id, alias, email).
-pub async fn get_all_games_for_rating(
+/// The join code of a game and the seconds it still works, if any
+pub async fn get_join_code(
     db: Db,
-) -> Result<Vec<(Game, Vec<(i64, String, String)>)>, Error> {
+    owner: String,
+    game_id: String,
+) -> Result<Option<(String, i64)>, Error> {
+    let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
+
+    require_role(&**db, gid, &owner, Role::Owner).await?;
+
+    let row = sqlx::query(
+        "SELECT code, expires_at - strftime('%s', 'now') AS left FROM join_code
+         WHERE game_id = ? AND expires_at >= strftime('%s', 'now')",
+    )
+    .bind(gid)
+    .fetch_optional(&**db)
+    .await?;
+
+    row.map(|r| Ok((r.try_get("code")?, r.try_get("left")?)))
+        .transpose()
+}
+
+/// The game behind a join code that still works, with its id
+pub async fn get_join_game(db: Db, code: &str) -> Result<(String, Game), Error> {
+    let game_id: i64 = sqlx::query_scalar(
+        "SELECT game_id FROM join_code
+         WHERE code = ? AND expires_at >= strftime('%s', 'now')",
+    )
+    .bind(code)
+    .fetch_optional(&**db)
+    .await?
+    .ok_or(Error::NoJoinCode)?;
+
+    Ok((game_id.to_string(), load_game(&db, game_id).await?))
+}
+
+/// Takes a seat in the game of a join code, which uses up the code.
+/// Returns the id of the game.
+pub async fn join_with_code(
+    db: Db,
+    email: String,
+    code: &str,
+    alias: String,
+) -> Result<String, Error> {
+    let mut tx = (**db).begin_with("BEGIN IMMEDIATE").await?;
+
+    let game_id: i64 = sqlx::query_scalar(
+        "DELETE FROM join_code
+         WHERE code = ? AND expires_at >= strftime('%s', 'now')
+         RETURNING game_id",
+    )
+    .bind(code)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(Error::NoJoinCode)?;
+
+    let login_id: i64 = sqlx::query_scalar("SELECT id FROM login WHERE email = ?")
+        .bind(&email)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let seat: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM player WHERE game_id = ? AND name = ?")
+            .bind(game_id)
+            .bind(&alias)
+            .fetch_one(&mut *tx)
+            .await?;
+    if seat == 0 {
+        return Err(Error::UnknownPlayer);
+    }
+
+    check_seat(&mut tx, game_id, login_id, &alias).await?;
+
+    sqlx::query("INSERT INTO plays (login_id, game_id, alias) VALUES (?, ?, ?)")
+        .bind(login_id)
+        .bind(game_id)
+        .bind(&alias)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM invite WHERE game_id = ? AND (login_id = ? OR alias = ?)")
+        .bind(game_id)
+        .bind(login_id)
+        .bind(&alias)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(game_id.to_string())
+}
+
+/// Stores a new API token of a user by its hash
+pub async fn add_api_token(db: Db, email: String, name: String, hash: String) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO api_token (login_id, name, hash)
+         SELECT id, ?, ? FROM login WHERE email = ?",
+    )
+    .bind(&name)
+    .bind(&hash)
+    .bind(&email)
+    .execute(&**db)
+    .await?;
+
+    Ok(())
+}
+
+/// All API tokens of a user, the newest first
+pub async fn get_api_tokens(db: Db, email: String) -> Result<Vec<ApiToken>, Error> {
     let rows = sqlx::query(
-        "SELECT g.id, g.game, p.login_id, p.alias, l.email
+        "SELECT t.id, t.name, t.last_used FROM api_token t
+         JOIN login l ON l.id = t.login_id
+         WHERE l.email = ?
+         ORDER BY t.id DESC",
+    )
+    .bind(&email)
+    .fetch_all(&**db)
+    .await?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(ApiToken {
+                id: r.try_get("id")?,
+                name: r.try_get("name")?,
+                last_used: r.try_get("last_used")?,
+            })
+        })
+        .collect()
+}
+
+/// Revokes an API token, only when it belongs to this user
+pub async fn remove_api_token(db: Db, email: String, id: i64) -> Result<(), Error> {
+    sqlx::query(
+        "DELETE FROM api_token
+         WHERE id = ? AND login_id = (SELECT id FROM login WHERE email = ?)",
+    )
+    .bind(id)
+    .bind(&email)
+    .execute(&**db)
+    .await?;
+
+    Ok(())
+}
+
+/// The email of the user an API token belongs to, which also marks the token as used
+pub async fn api_token_user(db: Db, hash: &str) -> Result<Option<String>, Error> {
+    let email = sqlx::query_scalar(
+        "UPDATE api_token SET last_used = strftime('%s', 'now')
+         WHERE hash = ?
+         RETURNING (SELECT email FROM login WHERE login.id = api_token.login_id)",
+    )
+    .bind(hash)
+    .fetch_optional(&**db)
+    .await?;
+
+    Ok(email)
+}
+
+/// A response that was given to a submission with an idempotency key
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// The response to an earlier submission with this key, if there was one
+pub async fn get_response(
+    db: Db,
+    game_id: String,
+    key: &str,
+) -> Result<Option<StoredResponse>, Error> {
+    let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
+
+    let row = sqlx::query(
+        "SELECT status, headers, body FROM idempotency_key
+         WHERE game_id = ? AND key = ?",
+    )
+    .bind(gid)
+    .bind(key)
+    .fetch_optional(&**db)
+    .await?;
+
+    row.map(|r| {
+        let headers: String = r.try_get("headers")?;
+        Ok(StoredResponse {
+            status: r.try_get("status")?,
+            headers: serde_json::from_str(&headers).map_err(|_| Error::NoGameError)?,
+            body: r.try_get("body")?,
+        })
+    })
+    .transpose()
+}
+
+/// Remembers the response to a submission, and forgets keys older than a day
+pub async fn save_response(
+    db: Db,
+    game_id: String,
+    key: &str,
+    response: &StoredResponse,
+) -> Result<(), Error> {
+    let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
+
+    let mut tx = (**db).begin().await?;
+    sqlx::query(
+        "DELETE FROM idempotency_key
+         WHERE game_id = ? AND created_at < strftime('%s', 'now') - 86400",
+    )
+    .bind(gid)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO idempotency_key (game_id, key, status, headers, body)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(gid)
+    .bind(key)
+    .bind(response.status)
+    .bind(serde_json::to_string(&response.headers).unwrap())
+    .bind(&response.body)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// A game paired with its linked plays (login_id, alias, email).
+pub type RatedGame = (Game, Vec<(i64, String, String)>);
+
+/// The rating of a player after a game
+pub struct RatingStep {
+    pub game_id: i64,
+    pub login_id: i64,
+    pub rating: Rating,
+    /// what the game added to the rating
+    pub delta: f64,
+}
+
+/// Returns the games from game `from` on, ordered by game ID, each with its ID
+/// and paired with their linked plays (login_id, alias, email).
+pub async fn get_games_for_rating(db: Db, from: i64) -> Result<Vec<(i64, RatedGame)>, Error> {
+    let rows = sqlx::query(
+        "SELECT g.id, p.login_id, p.alias, l.email
+         FROM game g
+         LEFT JOIN plays p ON p.game_id = g.id AND p.trashed_at IS NULL
+         LEFT JOIN login l ON l.id = p.login_id
+         WHERE g.id >= ? AND EXISTS (
+             SELECT 1 FROM plays a WHERE a.game_id = g.id AND a.trashed_at IS NULL
+         )
+         ORDER BY g.id",
+    )
+    .bind(from)
+    .fetch_all(&**db)
+    .await?;
+
+    rated_games(&db, rows).await
+}
+
+/// Like `get_games_for_rating`, for the games started
+/// from unix time `start` up to unix time `end`
+pub async fn get_games_started_between(
+    db: Db,
+    start: i64,
+    end: i64,
+) -> Result<Vec<(i64, RatedGame)>, Error> {
+    let rows = sqlx::query(
+        "SELECT g.id, p.login_id, p.alias, l.email
          FROM game g
-         LEFT JOIN plays p ON p.game_id = g.id
+         LEFT JOIN plays p ON p.game_id = g.id AND p.trashed_at IS NULL
          LEFT JOIN login l ON l.id = p.login_id
+         WHERE g.created_at >= ? AND g.created_at < ? AND EXISTS (
+             SELECT 1 FROM plays a WHERE a.game_id = g.id AND a.trashed_at IS NULL
+         )
          ORDER BY g.id",
     )
+    .bind(start)
+    .bind(end)
     .fetch_all(&**db)
     .await?;
 
-    let mut result: Vec<(i64, Game, Vec<(i64, String, String)>)> = Vec::new();
+    rated_games(&db, rows).await
+}
+
+/// Loads the games of rows of (id, login_id, alias, email), ordered by game ID
+async fn rated_games(db: &Db, rows: Vec<SqliteRow>) -> Result<Vec<(i64, RatedGame)>, Error> {
+    let mut result: Vec<(i64, RatedGame)> = Vec::new();
 
     for row in rows {
         let game_id: i64 = row.try_get("id")?;
-        if result.last().map(|(id, _, _)| *id) != Some(game_id) {
-            let json: String = row.try_get("game")?;
-            let game: Game = serde_json::from_str(&json).map_err(|_| Error::NoGameError)?;
-            result.push((game_id, game, vec![]));
+        if result.last().map(|(id, _)| *id) != Some(game_id) {
+            let game = load_game(db, game_id).await?;
+            result.push((game_id, (game, vec![])));
         }
         if let Ok(login_id) = row.try_get::<i64, _>("login_id") {
             let alias: String = row.try_get("alias").unwrap_or_default();
             let email: String = row.try_get("email").unwrap_or_default();
-            result.last_mut().unwrap().2.push((login_id, alias, email));
+            if let Some((_, (_, plays))) = result.last_mut() {
+                plays.push((login_id, alias, email));
+            }
         }
     }
 
-    Ok(result.into_iter().map(|(_, game, plays)| (game, plays)).collect())
+    Ok(result)
+}
+
+/// The ratings of all players after the games before game `before`
+pub async fn get_rating_base(db: Db, before: i64) -> Result<HashMap<i64, Rating>, Error> {
+    let rows = sqlx::query(
+        "SELECT s.login_id, s.elo, s.deviation, s.volatility FROM rating_step s
+         WHERE s.game_id = (
+             SELECT MAX(game_id) FROM rating_step
+             WHERE login_id = s.login_id AND game_id < ?
+         )",
+    )
+    .bind(before)
+    .fetch_all(&**db)
+    .await?;
+
+    rows.into_iter()
+        .map(|r| {
+            let rating = Rating {
+                rating: r.try_get("elo")?,
+                deviation: r.try_get("deviation")?,
+                volatility: r.try_get("volatility")?,
+            };
+            Ok((r.try_get("login_id")?, rating))
+        })
+        .collect()
 }
 
-/// Atomically replaces all rows in the rating table.
-pub async fn upsert_ratings(db: Db, ratings: &HashMap<i64, i32>) -> Result<(), Error> {
+/// The rating system and mode the rating steps were computed with,
+/// `None` when the ratings were never computed game by game
+pub async fn get_rating_system(db: Db) -> Result<Option<String>, Error> {
+    Ok(sqlx::query_scalar("SELECT system FROM rating_step LIMIT 1")
+        .fetch_optional(&**db)
+        .await?)
+}
+
+/// Replaces the rating steps from game `from` on, and the ratings of the players
+/// they were about. `ratings` holds the rating of every player after the last game.
+pub async fn save_rating_steps(
+    db: Db,
+    system: &str,
+    from: i64,
+    steps: &[RatingStep],
+    ratings: &HashMap<i64, Rating>,
+) -> Result<(), Error> {
     let mut tx = (**db).begin().await?;
-    sqlx::query("DELETE FROM rating").execute(&mut *tx).await?;
-    for (&login_id, &elo) in ratings {
-        sqlx::query("INSERT INTO rating (login_id, elo) VALUES (?, ?)")
-            .bind(login_id)
-            .bind(elo)
-            .execute(&mut *tx)
-            .await?;
+
+    let mut changed: HashSet<i64> =
+        sqlx::query_scalar("SELECT DISTINCT login_id FROM rating_step WHERE game_id >= ?")
+            .bind(from)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .collect();
+    changed.extend(steps.iter().map(|step| step.login_id));
+
+    if from == 0 {
+        // everybody is rated again
+        sqlx::query("DELETE FROM rating").execute(&mut *tx).await?;
+    }
+    sqlx::query("DELETE FROM rating_step WHERE game_id >= ?")
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+
+    for step in steps {
+        sqlx::query(
+            "INSERT INTO rating_step
+             (game_id, login_id, elo, delta, deviation, volatility, system)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(step.game_id)
+        .bind(step.login_id)
+        .bind(step.rating.rating)
+        .bind(step.delta)
+        .bind(step.rating.deviation)
+        .bind(step.rating.volatility)
+        .bind(system)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for login_id in changed {
+        match ratings.get(&login_id) {
+            Some(rating) => {
+                sqlx::query(
+                    "INSERT OR REPLACE INTO rating (login_id, elo, deviation, volatility)
+                     VALUES (?, ?, ?, ?)",
+                )
+                .bind(login_id)
+                .bind(rating.rating.round() as i32)
+                .bind(rating.deviation)
+                .bind(rating.volatility)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM rating WHERE login_id = ?")
+                    .bind(login_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
     }
     tx.commit().await?;
+
     Ok(())
 }
 
-/// Returns (email, elo) for all rated players, sorted descending by elo.
-pub async fn get_ratings(db: Db) -> Result<Vec<(String, i32)>, Error> {
+/// Returns (email, elo, rated games) for all rated players, sorted descending by elo.
+pub async fn get_ratings(db: Db) -> Result<Vec<(String, i32, i64)>, Error> {
     let rows = sqlx::query(
-        "SELECT l.email, r.elo FROM rating r
+        "SELECT l.email, r.elo,
+                (SELECT COUNT(*) FROM rating_step s WHERE s.login_id = r.login_id) AS games
+         FROM rating r
          JOIN login l ON l.id = r.login_id
          ORDER BY r.elo DESC",
     )
     .fetch_all(&**db)
     .await?;
     rows.into_iter()
-        .map(|r| Ok((r.try_get("email")?, r.try_get("elo")?)))
+        .map(|r| Ok((r.try_get("email")?, r.try_get("elo")?, r.try_get("games")?)))
+        .collect()
+}
+
+/// Whether the final standings of the season were archived
+pub async fn is_season_archived(db: Db, season: &str) -> Result<bool, Error> {
+    let archived: Option<i64> = sqlx::query_scalar("SELECT 1 FROM season_archive WHERE season = ?")
+        .bind(season)
+        .fetch_optional(&**db)
+        .await?;
+    Ok(archived.is_some())
+}
+
+/// Archives the final standings of a season, as (login_id, elo, rated games).
+/// A season is only archived once.
+pub async fn archive_season(
+    db: Db,
+    season: &str,
+    standings: &[(i64, i32, i64)],
+) -> Result<(), Error> {
+    let mut tx = (**db).begin().await?;
+
+    let archived = sqlx::query("INSERT OR IGNORE INTO season_archive (season) VALUES (?)")
+        .bind(season)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    if archived == 0 {
+        return Ok(());
+    }
+
+    for (login_id, elo, games) in standings {
+        sqlx::query(
+            "INSERT INTO season_standing (season, login_id, elo, games) VALUES (?, ?, ?, ?)",
+        )
+        .bind(season)
+        .bind(login_id)
+        .bind(elo)
+        .bind(games)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Returns (email, elo, rated games) for all players in the archived
+/// standings of a season, sorted descending by elo
+pub async fn get_season_standings(db: Db, season: &str) -> Result<Vec<(String, i32, i64)>, Error> {
+    let rows = sqlx::query(
+        "SELECT l.email, s.elo, s.games FROM season_standing s
+         JOIN login l ON l.id = s.login_id
+         WHERE s.season = ?
+         ORDER BY s.elo DESC",
+    )
+    .bind(season)
+    .fetch_all(&**db)
+    .await?;
+    rows.into_iter()
+        .map(|r| Ok((r.try_get("email")?, r.try_get("elo")?, r.try_get("games")?)))
+        .collect()
+}
+
+/// Returns the rating history of the player with this email, oldest game first.
+/// Games in the trash of the player are left out, as the ratings no longer count them.
+pub async fn get_rating_history(db: Db, email: String) -> Result<Vec<RatingChange>, Error> {
+    let rows = sqlx::query(
+        "SELECT s.game_id, g.name, s.elo, s.delta FROM rating_step s
+         JOIN login l ON l.id = s.login_id
+         JOIN game g ON g.id = s.game_id
+         WHERE l.email = ?
+         ORDER BY s.game_id",
+    )
+    .bind(email)
+    .fetch_all(&**db)
+    .await?;
+
+    rows.into_iter()
+        .map(|r| {
+            let elo: f64 = r.try_get("elo")?;
+            let delta: f64 = r.try_get("delta")?;
+            Ok(RatingChange {
+                game_id: r.try_get("game_id")?,
+                game: r.try_get("name")?,
+                rating: elo.round() as i32,
+                // rounded like the ratings, so that the deltas add up to them
+                delta: (elo.round() - (elo - delta).round()) as i32,
+            })
+        })
         .collect()
 }
 
@@ -295,9 +1539,9 @@ pub async fn get_game_players(db: Db, game_id: String) -> Result<Vec<LinkedPlaye
     let gid: i64 = game_id.parse().map_err(|_| Error::NoGameError)?;
 
     let rows = sqlx::query(
-        "SELECT p.alias, l.email FROM plays p
+        "SELECT p.alias, l.email, p.role FROM plays p
          JOIN login l ON l.id = p.login_id
-         WHERE p.game_id = ?",
+         WHERE p.game_id = ? AND p.trashed_at IS NULL",
     )
     .bind(gid)
     .fetch_all(&**db)
@@ -308,6 +1552,7 @@ pub async fn get_game_players(db: Db, game_id: String) -> Result<Vec<LinkedPlaye
             Ok(LinkedPlayer {
                 alias: r.try_get("alias")?,
                 email: r.try_get("email")?,
+                role: r.try_get::<String, _>("role")?.parse()?,
             })
         })
         .collect()
@@ -2,7 +2,7 @@ use std::fmt::Display;
 
 use http::StatusCode;
 
-use crate::template::AlertTemplate;
+use crate::template::{AlertTemplate, Role};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -12,10 +12,16 @@ pub enum Error {
     GameNameExists(String),
     #[error("geef 4 verschillende namen")]
     PlayerNameProblem,
+    #[error("{0} zit deze ronde uit")]
+    SittingOut(String),
+    #[error("kies wie er miserie speelt")]
+    NoMisery,
     #[error("Database error: {0}")]
     SqlxError(#[from] sqlx::Error),
     #[error("Migration error: {0}")]
     SqlxMigrateError(#[from] sqlx::migrate::MigrateError),
+    #[error("Game {0} in legacy_game could not be converted: {1}")]
+    LegacyGame(i64, String),
     #[error("Env Var decoding failed: {0}")]
     EnvVarDecodeError(base64::DecodeError),
     #[error("Please set the {0} env variable in .env or .env.dev")]
@@ -34,6 +40,42 @@ pub enum Error {
     ReqwestError(reqwest::Error),
     #[error("No gam efor this owner has been found")]
     NoGameError,
+    #[error("deze ronde bestaat niet")]
+    NoRound,
+    #[error("onbekende speler")]
+    UnknownPlayer,
+    #[error("onbekende gebruiker")]
+    UnknownUser,
+    #[error("{0} is al gekoppeld aan een account")]
+    SeatTaken(String),
+    #[error("die gebruiker speelt al mee in dit spel")]
+    AlreadyPlaying,
+    #[error("deze uitnodiging bestaat niet (meer)")]
+    NoInvite,
+    #[error("deze code werkt niet (meer)")]
+    NoJoinCode,
+    #[error("onbekende rol")]
+    UnknownRole,
+    #[error("onbekende puntentabel")]
+    UnknownRules,
+    #[error("{0}")]
+    UnknownBid(#[from] BidErr),
+    #[error("dat mag enkel een {0} van dit spel")]
+    NotAllowed(Role),
+    #[error("een spel heeft minstens één eigenaar nodig")]
+    LastOwner,
+    #[error("dit spel staat niet (meer) in je prullenbak")]
+    NotInTrash,
+    #[error("een correctie moet in totaal op nul uitkomen")]
+    NotZeroSum,
+    #[error("een correctie geeft of neemt hoogstens {0} punten per speler")]
+    CorrectionTooLarge(i16),
+    #[error("de scores worden te groot")]
+    ScoreOverflow,
+    #[error("dit spel is afgelopen")]
+    GameFinished,
+    #[error("iemand anders paste dit spel net aan, kijk het na en probeer opnieuw")]
+    Conflict,
     #[error("This was a bad login")]
     BadLogin,
     #[error("The password does not fulfil: {0}")]
@@ -55,6 +97,18 @@ pub enum LoginErr {
     WrongCreds,
 }
 
+/// A bid that could not be parsed
+#[derive(Debug)]
+pub struct BidErr(pub String);
+
+impl Display for BidErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "onbekend bod: \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for BidErr {}
+
 impl Display for LoginErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -76,8 +130,12 @@ impl Display for TokenError {
 
 impl Error {
     pub fn into_alert(self) -> AlertTemplate {
+        let code = match self {
+            Error::NotAllowed(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        };
         AlertTemplate {
-            code: StatusCode::BAD_REQUEST,
+            code,
             alert: self.to_string(),
         }
     }
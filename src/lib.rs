@@ -3,7 +3,10 @@ pub mod config;
 pub mod db;
 pub mod embed;
 pub mod error;
+pub mod live;
 pub mod rating;
+pub mod scoring;
+pub mod season;
 pub mod telegram;
 pub mod template;
 pub mod whist;
@@ -0,0 +1,53 @@
+//! Pushes the changes of a game to everybody that has it open.
+//!
+//! Every game that is being watched gets a broadcast channel. The updates carry
+//! the game itself, so that every open view can render it on its own.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use tokio::sync::broadcast;
+
+use crate::template::PlayerLinkStatus;
+use crate::whist::Game;
+
+/// How many updates a slow view can fall behind before it skips some
+const CAPACITY: usize = 16;
+
+static CHANNELS: OnceLock<Mutex<HashMap<String, broadcast::Sender<Update>>>> = OnceLock::new();
+
+/// A change of a game
+#[derive(Clone)]
+pub enum Update {
+    /// A round was added to the game
+    Points(Game),
+    /// The game changed in another way, like an undo or an edited round
+    Game(Game),
+    /// The players of the game were linked to other accounts or got other roles
+    Settings(Game, Vec<PlayerLinkStatus>),
+}
+
+fn channels() -> &'static Mutex<HashMap<String, broadcast::Sender<Update>>> {
+    CHANNELS.get_or_init(Default::default)
+}
+
+/// Starts listening to the updates of a game
+pub fn subscribe(game_id: &str) -> broadcast::Receiver<Update> {
+    channels()
+        .lock()
+        .unwrap()
+        .entry(game_id.to_string())
+        .or_insert_with(|| broadcast::channel(CAPACITY).0)
+        .subscribe()
+}
+
+/// Sends an update to every open view of the game
+pub fn publish(game_id: &str, update: Update) {
+    let mut channels = channels().lock().unwrap();
+    if let Some(sender) = channels.get(game_id) {
+        if sender.send(update).is_err() {
+            // nobody is watching anymore
+            channels.remove(game_id);
+        }
+    }
+}
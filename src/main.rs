@@ -1,5 +1,7 @@
 // Re-export lib items so routes.rs can use crate:: paths unchanged
-pub use whistbook::{auth, config, db, embed, error, rating, telegram, template, whist};
+pub use whistbook::{
+    auth, config, db, embed, error, live, rating, scoring, season, telegram, template, whist,
+};
 pub use whistbook::{config as config_fn, config_bytes};
 pub use whistbook::Db;
 
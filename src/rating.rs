@@ -1,80 +1,659 @@
 use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
 
-use crate::whist::Game;
-use crate::{db, Db};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::db::{RatedGame, RatingStep};
 use crate::error::Error;
+use crate::scoring::ScoringRules;
+use crate::whist::{Game, Round};
+use crate::{db, season, Db};
 
 pub const DEFAULT_RATING: i32 = 1000;
 const K: f64 = 32.0;
+/// The ELO K factor of one pair in a deal, before the weight of the bid
+const DEAL_K: f64 = 4.0;
+/// In deal mode, a bid worth this many points counts once, harder bids count more
+const DEAL_WEIGHT_POINTS: f64 = 8.0;
+/// The rating deviation of a player without rated games
+pub const DEFAULT_DEVIATION: f64 = 350.0;
+/// The volatility of a player without rated games
+pub const DEFAULT_VOLATILITY: f64 = 0.06;
+/// Players with fewer rated games have a provisional rating
+pub const PROVISIONAL_GAMES: i64 = 5;
 
-/// Pure computation: takes all games with their plays (ordered by game ID ascending),
-/// returns login_id → elo map.
-///
-/// Uses per-game pairwise ELO: for each game, rank linked players by their current
-/// cumulative score and apply pairwise ELO updates (higher score beats lower score).
+/// How long the worker waits for more changes before it updates the ratings
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// How long the worker waits at most, while the changes keep coming
+const MAX_DELAY: Duration = Duration::from_secs(5);
+/// How often the worker looks for seasons that ended, when nothing changes
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+static WORKER: OnceLock<mpsc::UnboundedSender<Change>> = OnceLock::new();
+
+/// A change that the ratings have to follow
+pub enum Change {
+    /// The rounds, settings or linked players of a game changed, or it was
+    /// deleted: the games from this one on have to be rated again
+    Game(String),
+}
+
+impl Change {
+    /// The first game whose rating has to be computed again
+    fn first_game(&self) -> i64 {
+        match self {
+            Change::Game(id) => id.parse().unwrap_or(0),
+        }
+    }
+}
+
+/// The rating of a player. Only Glicko-2 uses the deviation and the volatility,
+/// with the ELO system they keep their default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: DEFAULT_RATING as f64,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+
+/// A way to rate the linked players of a game
+pub trait RatingSystem: Send + Sync {
+    /// The name of the system in the config and in the database
+    fn name(&self) -> &'static str;
+
+    /// Takes the ratings of the linked players before a game, each with their
+    /// final score, and returns their ratings after the game, in the same order.
+    fn rate(&self, players: &[(Rating, i16)]) -> Vec<Rating>;
+
+    /// Rates one side of a deal against its opponents, both given as indexes into
+    /// `ratings`: every player of the team played every opponent, and won if the team
+    /// made its bid. `weight` is how much the deal counts, one for an average bid.
+    fn rate_deal(
+        &self,
+        ratings: &mut [Rating],
+        team: &[usize],
+        opponents: &[usize],
+        made: bool,
+        weight: f64,
+    );
+}
+
+/// What the ratings are computed from, chosen with `RATING_MODE` in the config
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// The final scores of every game, the default
+    Game,
+    /// Every deal, as the playing team against the opponents
+    Deal,
+}
+
+impl Mode {
+    pub fn from_config() -> Self {
+        match crate::config("RATING_MODE").map(String::as_str) {
+            Ok("deal") => Mode::Deal,
+            _ => Mode::Game,
+        }
+    }
+}
+
+/// The name the ratings are stored with, so that a change
+/// of rating system or mode rates all games again
+fn method(system: &dyn RatingSystem, mode: Mode) -> String {
+    match mode {
+        Mode::Game => system.name().to_string(),
+        Mode::Deal => format!("{}-deal", system.name()),
+    }
+}
+
+/// Pairwise ELO: the linked players are ranked by their final score and every
+/// pair gets an ELO update (higher score beats lower score, a tie changes nothing).
 /// K is divided by (n_linked - 1) so that total ELO impact per game stays constant
 /// regardless of how many players are linked.
-pub fn compute_ratings(games: &[(Game, Vec<(i64, String, String)>)]) -> HashMap<i64, i32> {
-    let mut ratings: HashMap<i64, f64> = HashMap::new();
-
-    for (game, plays) in games {
-        let alias_to_id: HashMap<&str, i64> = plays
-            .iter()
-            .map(|(id, alias, _)| (alias.as_str(), *id))
-            .collect();
+/// In deal mode every player of the team and every opponent form a pair.
+pub struct Elo;
 
-        // Collect (login_id, final_score) for each linked player position
-        let final_scores = game.last_score();
-        let linked: Vec<(i64, i16)> = (&game.players)
-            .into_iter()
-            .enumerate()
-            .filter_map(|(pos, name)| {
-                alias_to_id
-                    .get(name.as_str())
-                    .map(|&id| (id, final_scores.0[pos]))
-            })
-            .collect();
+impl RatingSystem for Elo {
+    fn name(&self) -> &'static str {
+        "elo"
+    }
 
-        let n = linked.len();
+    fn rate(&self, players: &[(Rating, i16)]) -> Vec<Rating> {
+        let mut ratings: Vec<Rating> = players.iter().map(|(rating, _)| *rating).collect();
+        let n = players.len();
         if n < 2 {
-            continue;
+            return ratings;
         }
 
         let k_pair = K / (n - 1) as f64;
 
         for i in 0..n {
             for j in (i + 1)..n {
-                let (id_i, score_i) = linked[i];
-                let (id_j, score_j) = linked[j];
+                let (score_i, score_j) = (players[i].1, players[j].1);
 
                 if score_i == score_j {
                     continue; // tie — no update
                 }
 
-                let (winner, loser) = if score_i > score_j {
-                    (id_i, id_j)
+                let (winner, loser) = if score_i > score_j { (i, j) } else { (j, i) };
+
+                let r_w = ratings[winner].rating;
+                let r_l = ratings[loser].rating;
+                let e_w = 1.0 / (1.0 + 10_f64.powf((r_l - r_w) / 400.0));
+                ratings[winner].rating = r_w + k_pair * (1.0 - e_w);
+                ratings[loser].rating = r_l + k_pair * (0.0 - (1.0 - e_w));
+            }
+        }
+
+        ratings
+    }
+
+    fn rate_deal(
+        &self,
+        ratings: &mut [Rating],
+        team: &[usize],
+        opponents: &[usize],
+        made: bool,
+        weight: f64,
+    ) {
+        let k_pair = DEAL_K * weight;
+
+        for &player in team {
+            for &opponent in opponents {
+                let (winner, loser) = if made {
+                    (player, opponent)
                 } else {
-                    (id_j, id_i)
+                    (opponent, player)
                 };
 
-                let r_w = *ratings.get(&winner).unwrap_or(&(DEFAULT_RATING as f64));
-                let r_l = *ratings.get(&loser).unwrap_or(&(DEFAULT_RATING as f64));
+                let r_w = ratings[winner].rating;
+                let r_l = ratings[loser].rating;
                 let e_w = 1.0 / (1.0 + 10_f64.powf((r_l - r_w) / 400.0));
-                ratings.insert(winner, r_w + k_pair * (1.0 - e_w));
-                ratings.insert(loser, r_l + k_pair * (0.0 - (1.0 - e_w)));
+                ratings[winner].rating = r_w + k_pair * (1.0 - e_w);
+                ratings[loser].rating = r_l + k_pair * (0.0 - (1.0 - e_w));
             }
         }
     }
+}
 
-    ratings
+/// Glicko-2: every game is a rating period for the linked players in it, in which
+/// they played every other linked player, winning from the lower final scores.
+/// In deal mode every deal is a rating period of its own.
+/// A player with few games has a high deviation, so their rating moves a lot,
+/// and it settles as the deviation shrinks with every game.
+pub struct Glicko2;
+
+/// Converts between the Glicko rating scale and the Glicko-2 scale
+const GLICKO2_SCALE: f64 = 173.7178;
+/// How much the volatility can change between games
+const TAU: f64 = 0.5;
+/// The precision of the new volatility
+const EPSILON: f64 = 0.000001;
+
+impl Glicko2 {
+    fn g(phi: f64) -> f64 {
+        1.0 / (1.0 + 3.0 * phi * phi / (std::f64::consts::PI * std::f64::consts::PI)).sqrt()
+    }
+
+    fn expected(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+        1.0 / (1.0 + (-Self::g(phi_j) * (mu - mu_j)).exp())
+    }
+
+    /// The new volatility, found with the Illinois algorithm
+    fn volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+        let a = (sigma * sigma).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+                - (x - a) / (TAU * TAU)
+        };
+
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+        while (big_b - big_a).abs() > EPSILON {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = big_c;
+            f_b = f_c;
+        }
+
+        (big_a / 2.0).exp()
+    }
+
+    /// The rating after a rating period with these results,
+    /// each as the opponent, the outcome and how much it counts
+    fn update(rating: &Rating, results: &[(Rating, f64, f64)]) -> Rating {
+        let scale = |rating: &Rating| {
+            (
+                (rating.rating - DEFAULT_RATING as f64) / GLICKO2_SCALE,
+                rating.deviation / GLICKO2_SCALE,
+            )
+        };
+        let (mu, phi) = scale(rating);
+
+        let mut v_inv = 0.0;
+        let mut improvement = 0.0;
+        for (other, outcome, weight) in results {
+            let (mu_j, phi_j) = scale(other);
+            let g = Self::g(phi_j);
+            let e = Self::expected(mu, mu_j, phi_j);
+            // a weight counts as that many games with the same outcome
+            v_inv += weight * g * g * e * (1.0 - e);
+            improvement += weight * g * (outcome - e);
+        }
+        let v = 1.0 / v_inv;
+
+        let volatility = Self::volatility(phi, rating.volatility, v, v * improvement);
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let new_mu = mu + new_phi * new_phi * improvement;
+
+        Rating {
+            rating: new_mu * GLICKO2_SCALE + DEFAULT_RATING as f64,
+            deviation: new_phi * GLICKO2_SCALE,
+            volatility,
+        }
+    }
+}
+
+impl RatingSystem for Glicko2 {
+    fn name(&self) -> &'static str {
+        "glicko2"
+    }
+
+    fn rate(&self, players: &[(Rating, i16)]) -> Vec<Rating> {
+        if players.len() < 2 {
+            return players.iter().map(|(rating, _)| *rating).collect();
+        }
+
+        // everyone is rated against the ratings before the game
+        players
+            .iter()
+            .enumerate()
+            .map(|(i, (rating, score))| {
+                let results: Vec<(Rating, f64, f64)> = players
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, (other, other_score))| {
+                        let outcome = match score.cmp(other_score) {
+                            std::cmp::Ordering::Greater => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Less => 0.0,
+                        };
+                        (*other, outcome, 1.0)
+                    })
+                    .collect();
+                Self::update(rating, &results)
+            })
+            .collect()
+    }
+
+    fn rate_deal(
+        &self,
+        ratings: &mut [Rating],
+        team: &[usize],
+        opponents: &[usize],
+        made: bool,
+        weight: f64,
+    ) {
+        let (team_outcome, opponent_outcome) = if made { (1.0, 0.0) } else { (0.0, 1.0) };
+        let against = |players: &[usize], outcome| -> Vec<(Rating, f64, f64)> {
+            players
+                .iter()
+                .map(|&other| (ratings[other], outcome, weight))
+                .collect()
+        };
+
+        // everyone is rated against the ratings before the deal
+        let updates: Vec<(usize, Rating)> = team
+            .iter()
+            .map(|&player| {
+                let results = against(opponents, team_outcome);
+                (player, Self::update(&ratings[player], &results))
+            })
+            .chain(opponents.iter().map(|&player| {
+                let results = against(team, opponent_outcome);
+                (player, Self::update(&ratings[player], &results))
+            }))
+            .collect();
+
+        for (player, rating) in updates {
+            ratings[player] = rating;
+        }
+    }
+}
+
+/// The rating system chosen with `RATING_SYSTEM` in the config, ELO by default
+pub fn system() -> &'static dyn RatingSystem {
+    match crate::config("RATING_SYSTEM").map(String::as_str) {
+        Ok("glicko2") => &Glicko2,
+        _ => &Elo,
+    }
+}
+
+/// Pure computation: takes the ratings before the games and the games with their
+/// plays (ordered by game ID ascending), updates the ratings and returns the timeline
+/// of rating changes, one step for every player whose rating a game changed.
+///
+/// In game mode every game is rated with the final score of its linked players,
+/// so corrections such as renonce penalties count too. In deal mode the deals
+/// are rated one by one, and corrections and passes do not count.
+pub fn compute_ratings(
+    system: &dyn RatingSystem,
+    mode: Mode,
+    ratings: &mut HashMap<i64, Rating>,
+    games: &[(i64, RatedGame)],
+) -> Vec<RatingStep> {
+    let mut steps = vec![];
+
+    for (game_id, (game, plays)) in games {
+        for (login_id, before) in rate_game(system, mode, ratings, game, plays) {
+            let rating = ratings[&login_id];
+            steps.push(RatingStep {
+                game_id: *game_id,
+                login_id,
+                rating,
+                delta: rating.rating - before.rating,
+            });
+        }
+    }
+
+    steps
+}
+
+/// Applies the rating updates of one game to `ratings`, and returns
+/// the login ids whose rating it changed, with their rating before the game.
+fn rate_game(
+    system: &dyn RatingSystem,
+    mode: Mode,
+    ratings: &mut HashMap<i64, Rating>,
+    game: &Game,
+    plays: &[(i64, String, String)],
+) -> Vec<(i64, Rating)> {
+    let alias_to_id: HashMap<&str, i64> = plays
+        .iter()
+        .map(|(id, alias, _)| (alias.as_str(), *id))
+        .collect();
+
+    // Collect (player position, login_id) for each linked player
+    let linked: Vec<(usize, i64)> = (&game.players)
         .into_iter()
-        .map(|(id, elo)| (id, elo.round() as i32))
-        .collect()
+        .enumerate()
+        .filter_map(|(pos, name)| alias_to_id.get(name.as_str()).map(|&id| (pos, id)))
+        .collect();
+
+    if linked.len() < 2 {
+        return vec![];
+    }
+
+    let before: Vec<Rating> = linked
+        .iter()
+        .map(|(_, id)| ratings.get(id).copied().unwrap_or_default())
+        .collect();
+
+    let after = match mode {
+        Mode::Game => {
+            let final_scores = game.last_score();
+            let players: Vec<(Rating, i16)> = linked
+                .iter()
+                .zip(&before)
+                .map(|(&(pos, _), rating)| (*rating, final_scores.0[pos]))
+                .collect();
+            system.rate(&players)
+        }
+        Mode::Deal => rate_deals(system, game, &linked, before.clone()),
+    };
+
+    let mut changed = vec![];
+    for ((&(_, id), &old), new) in linked.iter().zip(&before).zip(after) {
+        if new != old {
+            ratings.insert(id, new);
+            changed.push((id, old));
+        }
+    }
+    changed
 }
 
-/// Fetches all game data, computes ELO ratings, and atomically writes them to the DB.
+/// Rates the deals of a game one by one, for the `linked` players
+/// with these ratings before the game
+fn rate_deals(
+    system: &dyn RatingSystem,
+    game: &Game,
+    linked: &[(usize, i64)],
+    mut ratings: Vec<Rating>,
+) -> Vec<Rating> {
+    let index: HashMap<usize, usize> = linked
+        .iter()
+        .enumerate()
+        .map(|(i, &(pos, _))| (pos, i))
+        .collect();
+    let linked_of = |seats: &[usize]| -> Vec<usize> {
+        seats
+            .iter()
+            .filter_map(|seat| index.get(seat).copied())
+            .collect()
+    };
+
+    for round in &game.deals {
+        let Round::Deal(deal) = round else {
+            continue;
+        };
+
+        // harder bids are worth more points, and count more
+        let (Ok(points), Ok(sides)) = (
+            game.rules.points(&deal.bid, deal.bid.tricks()),
+            deal.sides(&game.rules),
+        ) else {
+            continue;
+        };
+        let weight = (points.abs() as f64 / DEAL_WEIGHT_POINTS).sqrt();
+
+        for (team, opponents, made) in sides {
+            let (team, opponents) = (linked_of(&team), linked_of(&opponents));
+            if team.is_empty() || opponents.is_empty() {
+                continue;
+            }
+            system.rate_deal(&mut ratings, &team, &opponents, made, weight);
+        }
+    }
+
+    ratings
+}
+
+/// Fetches all game data, computes the ratings, and atomically writes them to the DB.
 pub async fn recompute_all(db: Db) -> Result<(), Error> {
-    let games = db::get_all_games_for_rating(db.clone()).await?;
-    let ratings = compute_ratings(&games);
-    db::upsert_ratings(db, &ratings).await
+    recompute_from(db, 0).await
+}
+
+/// Computes the ratings again from game `from` on, starting from the ratings
+/// after the games before it, and writes them to the DB.
+async fn recompute_from(db: Db, from: i64) -> Result<(), Error> {
+    let mut ratings = db::get_rating_base(db.clone(), from).await?;
+    let games = rated(db::get_games_for_rating(db.clone(), from).await?);
+
+    let (system, mode) = (system(), Mode::from_config());
+    let steps = compute_ratings(system, mode, &mut ratings, &games);
+    db::save_rating_steps(db, &method(system, mode), from, &steps, &ratings).await
+}
+
+/// Leaves out the games that do not count for the ratings
+fn rated(mut games: Vec<(i64, RatedGame)>) -> Vec<(i64, RatedGame)> {
+    if crate::config("RATE_FINISHED_ONLY").is_ok_and(|flag| flag == "true") {
+        games.retain(|(_, (game, _))| game.finished);
+    }
+    games
+}
+
+/// Rates the games started from unix time `start` up to `end` on their own,
+/// as if nobody had played before. Returns (login_id, email, elo, rated games)
+/// for all rated players, sorted descending by elo.
+pub async fn standings_between(
+    db: Db,
+    start: i64,
+    end: i64,
+) -> Result<Vec<(i64, String, i32, i64)>, Error> {
+    let games = rated(db::get_games_started_between(db, start, end).await?);
+
+    let mut ratings = HashMap::new();
+    let steps = compute_ratings(system(), Mode::from_config(), &mut ratings, &games);
+
+    let emails: HashMap<i64, &str> = games
+        .iter()
+        .flat_map(|(_, (_, plays))| plays)
+        .map(|(id, _, email)| (*id, email.as_str()))
+        .collect();
+    let mut games: HashMap<i64, i64> = HashMap::new();
+    for step in &steps {
+        *games.entry(step.login_id).or_default() += 1;
+    }
+
+    let mut standings: Vec<(i64, String, i32, i64)> = ratings
+        .into_iter()
+        .map(|(id, rating)| {
+            let email = emails.get(&id).copied().unwrap_or_default().to_string();
+            (id, email, rating.rating.round() as i32, games[&id])
+        })
+        .collect();
+    standings.sort_by_key(|&(_, _, elo, _)| std::cmp::Reverse(elo));
+    Ok(standings)
+}
+
+/// Archives the final standings of the seasons that have ended
+async fn archive_seasons(db: Db) -> Result<(), Error> {
+    for season in season::seasons().iter().filter(|season| season.has_ended()) {
+        if db::is_season_archived(db.clone(), &season.name).await? {
+            continue;
+        }
+
+        let standings: Vec<(i64, i32, i64)> =
+            standings_between(db.clone(), season.start, season.end)
+                .await?
+                .into_iter()
+                .map(|(id, _, elo, games)| (id, elo, games))
+                .collect();
+        db::archive_season(db.clone(), &season.name, &standings).await?;
+        log::info!("archived the standings of season {}", season.name);
+    }
+
+    Ok(())
+}
+
+/// Starts the worker that keeps the ratings up to date, one update at a time
+pub fn start_worker(db: Db) {
+    let (sender, changes) = mpsc::unbounded_channel();
+    if WORKER.set(sender).is_ok() {
+        tokio::spawn(worker(db, changes));
+    }
+}
+
+/// Has the worker update the ratings after a change
+pub fn update(change: Change) {
+    if let Some(worker) = WORKER.get() {
+        let _ = worker.send(change);
+    }
+}
+
+async fn worker(db: Db, mut changes: mpsc::UnboundedReceiver<Change>) {
+    // the ratings were only ever computed all at once before,
+    // or with another rating system or mode
+    let computed_with = db::get_rating_system(db.clone()).await.unwrap_or(None);
+    if computed_with != Some(method(system(), Mode::from_config())) {
+        if let Err(e) = recompute_all(db.clone()).await {
+            log::error!("computing the ratings failed: {e}");
+        }
+    }
+    if let Err(e) = archive_seasons(db.clone()).await {
+        log::error!("archiving the seasons failed: {e}");
+    }
+
+    loop {
+        let change = match tokio::time::timeout(ARCHIVE_INTERVAL, changes.recv()).await {
+            Ok(Some(change)) => change,
+            Ok(None) => break,
+            // seasons also end while nothing changes
+            Err(_) => {
+                if let Err(e) = archive_seasons(db.clone()).await {
+                    log::error!("archiving the seasons failed: {e}");
+                }
+                continue;
+            }
+        };
+        let mut from = change.first_game();
+
+        // changes that come in quick succession are handled at once
+        let deadline = Instant::now() + MAX_DELAY;
+        loop {
+            let wait = (Instant::now() + DEBOUNCE).min(deadline);
+            match tokio::time::timeout_at(wait, changes.recv()).await {
+                Ok(Some(change)) => from = from.min(change.first_game()),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        if let Err(e) = recompute_from(db.clone(), from).await {
+            log::error!("updating the ratings failed: {e}");
+        }
+        if let Err(e) = archive_seasons(db.clone()).await {
+            log::error!("archiving the seasons failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating: rating - 1500.0 + DEFAULT_RATING as f64,
+            deviation,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+
+    /// The example in Glickman's "Example of the Glicko-2 system",
+    /// moved from a start of 1500 to ours
+    #[test]
+    fn glicko2_follows_glickman() {
+        let player = rating(1500.0, 200.0);
+        let results = [
+            (rating(1400.0, 30.0), 1.0, 1.0),
+            (rating(1550.0, 100.0), 0.0, 1.0),
+            (rating(1700.0, 300.0), 0.0, 1.0),
+        ];
+
+        let new = Glicko2::update(&player, &results);
+        assert!((new.rating - rating(1464.06, 0.0).rating).abs() < 0.01);
+        assert!((new.deviation - 151.52).abs() < 0.01);
+        assert!((new.volatility - 0.05999).abs() < 0.00001);
+    }
 }
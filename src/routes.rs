@@ -1,17 +1,21 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
 
 use askama::Template;
-use axum::extract::{Path, Request, State};
-use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{HeaderMap, HeaderName, StatusCode, Uri};
 use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post, Router};
 use axum::Form;
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
 use axum_garde::WithValidation;
+use futures_util::Stream;
 use garde::Validate;
-use http::header::{COOKIE, SET_COOKIE};
+use http::header::{AUTHORIZATION, COOKIE, SET_COOKIE};
 use http::HeaderValue;
 use serde::{Deserialize, Serialize};
 use tower_governor::governor::GovernorConfigBuilder;
@@ -19,12 +23,18 @@ use tower_governor::key_extractor::KeyExtractor;
 use tower_governor::{GovernorError, GovernorLayer};
 use tower_http::compression::CompressionLayer;
 use tower_http::trace::TraceLayer;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::OwnedMutexGuard;
 use tower_livereload::LiveReloadLayer;
 
 use crate::auth;
 use crate::db;
 use crate::embed::StaticFile;
-use crate::error::Error;
+use crate::error::{BidErr, Error};
+use crate::live::{self, Update};
+use crate::rating::{self, Change};
+use crate::scoring::RuleSet;
+use crate::season::Period;
 use crate::template::*;
 use crate::whist::*;
 use crate::Db;
@@ -61,6 +71,8 @@ macro_rules! int_err {
     };
 }
 
+mod api;
+
 /// Reads the value of a named cookie from a raw `Cookie` header string.
 fn get_cookie_value<'a>(cookie_header: &'a str, name: &str) -> Option<&'a str> {
     cookie_header.split(';').find_map(|part| {
@@ -121,6 +133,30 @@ async fn refresh_middleware(mut req: Request, next: Next) -> Response {
     response
 }
 
+/// Middleware that lets scripts log in with an API token in an `Authorization: Bearer`
+/// header. For a token that was not revoked, a fresh access token is injected into the
+/// request cookies, so that the `auth!` checks of the handlers accept it.
+async fn bearer_middleware(State(db): State<Db>, mut req: Request, next: Next) -> Response {
+    let hash = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| auth::hash_api_token(t.trim()));
+
+    if let Some(hash) = hash {
+        if let Ok(Some(user)) = db::api_token_user(db, &hash).await {
+            if let Ok(token) = auth::create_token(user) {
+                if let Ok(val) = HeaderValue::from_str(&format!("token={token}")) {
+                    req.headers_mut().insert(COOKIE, val);
+                }
+            }
+        }
+    }
+
+    next.run(req).await
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 struct RateLimitToken;
 
@@ -137,6 +173,11 @@ impl KeyExtractor for RateLimitToken {
     }
 }
 
+/// How long a game stays in the trash before it is gone for good, in seconds
+const TRASH_WINDOW: i64 = 30 * 24 * 60 * 60;
+/// How often the trash is emptied
+const TRASH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub async fn router(app_state: Db) -> Router {
     let governor_conf = Arc::new(
         GovernorConfigBuilder::default()
@@ -163,6 +204,21 @@ pub async fn router(app_state: Db) -> Router {
         governor_limiter.retain_recent();
     });
 
+    rating::start_worker(app_state.clone());
+
+    // a separate background task to empty the trash and clean up orphaned games
+    let trash_db = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TRASH_INTERVAL);
+        loop {
+            interval.tick().await;
+            // the ratings stopped counting what is in the trash already
+            if let Err(e) = db::purge_trash(trash_db.clone(), TRASH_WINDOW).await {
+                log::error!("emptying the trash failed: {e}");
+            }
+        }
+    });
+
     let router = axum::Router::new()
         .route("/", get(index))
         .route("/login", get(login))
@@ -171,20 +227,55 @@ pub async fn router(app_state: Db) -> Router {
         .route("/api/logout", get(logout))
         .route("/api/qr", get(user_qr))
         .route("/form/:game_id", get(deal_form))
+        .route("/form/:game_id/:round", get(edit_deal_form))
         .route("/games", get(games))
         .route("/game/:game_id", get(game))
         .route("/game/:game_id", delete(delete_game))
+        .route("/trash", get(trash_page))
+        .route("/api/trash/:game_id/restore", post(restore_game))
         .route("/api/deal/:game_id", post(deal))
+        .route("/api/deal/:game_id/:round", delete(delete_deal))
         .route("/api/undo/:game_id", post(undo))
+        .route("/api/redo/:game_id", post(redo))
+        .route("/correction/:game_id", get(correction_form))
+        .route("/api/correction/:game_id", post(correction))
+        .route("/api/pass/:game_id", post(all_pass))
         .route("/game/:game_id/settings", get(game_settings))
-        .route("/api/game/:game_id/link-player", post(link_player))
+        .route("/api/game/:game_id/link-player", post(invite_player))
+        .route("/api/game/:game_id/role", post(set_role))
+        .route("/api/game/:game_id/dealer", post(set_dealer))
+        .route("/api/game/:game_id/end", post(set_end))
+        .route("/api/game/:game_id/finish", post(finish_game))
+        .route("/api/game/:game_id/reopen", post(reopen_game))
         .route("/leaderboard", get(leaderboard_page))
+        .route("/leaderboard/:email", get(rating_history_page))
+        .route("/tokens", get(api_tokens_page))
+        .route("/api/tokens", post(create_api_token))
+        .route("/api/tokens/:token_id", delete(revoke_api_token))
+        .route("/invites", get(invites_page))
+        .route("/api/invites/:invite_id/accept", post(accept_invite))
+        .route("/api/invites/:invite_id/decline", post(decline_invite))
         .route("/new-game", get(new_game_form))
         .route("/api/new-game", post(new_game))
         .route("/api/check-email", post(check_email))
         .route("/public/*file", get(static_handler))
         .route("/api/chart/:game_id", get(chart))
+        .route("/api/live/:game_id", get(live_updates))
+        .route("/game/:game_id/share", get(share_section))
+        .route("/api/game/:game_id/share", post(share_game))
+        .route("/api/game/:game_id/share", delete(unshare_game))
+        .route("/share/:game_id/:signature", get(shared_game))
+        .route("/share/:game_id/:signature/live", get(shared_live_updates))
+        .route("/game/:game_id/join-code", get(join_code_section))
+        .route("/api/game/:game_id/join-code", post(create_join_code))
+        .route("/join", get(join_page))
+        .route("/api/join", post(join_game))
+        .nest("/api/v1", api::router())
         .route_layer(middleware::from_fn(refresh_middleware))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            bearer_middleware,
+        ))
         .layer(TraceLayer::new_for_http())
         .layer(CompressionLayer::new())
         .with_state(app_state);
@@ -276,31 +367,260 @@ async fn main_page(db: Db, email: &str) -> axum::http::Response<axum::body::Body
     let ratings = db::get_ratings(db.clone()).await.unwrap_or_default();
     let rating = ratings
         .iter()
-        .find(|(e, _)| e == email)
-        .map(|(_, r)| *r)
+        .find(|(e, _, _)| e == email)
+        .map(|(_, r, _)| *r)
         .unwrap_or(crate::rating::DEFAULT_RATING);
-    HtmlTemplate(MainTemplate { rating }).into_response()
+    let invites = db::count_invites(db, email.to_string())
+        .await
+        .unwrap_or_default();
+    HtmlTemplate(MainTemplate {
+        email: email.to_string(),
+        rating,
+        invites,
+    })
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct LeaderboardQuery {
+    #[serde(default)]
+    periode: String,
+    /// Kept as text, so that an empty or odd minimum shows everybody
+    minimum: Option<String>,
 }
 
 async fn leaderboard_page(
     headers: HeaderMap,
     State(db): State<Db>,
+    Query(query): Query<LeaderboardQuery>,
     jar: CookieJar,
-) -> Result<Response, impl IntoResponse> {
-    auth!(jar, _token, {
-        let leaderboard = db::get_ratings(db)
-            .await
-            .unwrap_or_default()
-            .into_iter()
-            .map(|(email, rating)| LeaderboardEntry { email, rating })
-            .collect::<Vec<_>>();
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        _token,
+        {
+            let period = Period::parse(&query.periode);
+            let standings = match (&period, period.range()) {
+                (Period::Season(season), _)
+                    if db::is_season_archived(db.clone(), &season.name).await? =>
+                {
+                    db::get_season_standings(db, &season.name).await?
+                }
+                (_, Some((start, end))) => rating::standings_between(db, start, end)
+                    .await?
+                    .into_iter()
+                    .map(|(_, email, elo, games)| (email, elo, games))
+                    .collect(),
+                (_, None) => db::get_ratings(db).await?,
+            };
 
-        if !headers.contains_key("HX-Request") {
-            return Ok(HtmlTemplate(FullLeaderboardTemplate { leaderboard }).into_response());
-        }
+            let minimum = query
+                .minimum
+                .and_then(|minimum| minimum.trim().parse::<i64>().ok())
+                .unwrap_or_default()
+                .max(0);
+            let leaderboard = standings
+                .into_iter()
+                .filter(|(_, _, games)| *games >= minimum)
+                .map(LeaderboardEntry::new)
+                .collect::<Vec<_>>();
+            let periods = Period::choices()
+                .iter()
+                .map(|period| (period.value(), period.label()))
+                .collect();
+            let period = period.value();
+
+            if !headers.contains_key("HX-Request") {
+                return Ok(HtmlTemplate(FullLeaderboardTemplate {
+                    leaderboard,
+                    periods,
+                    period,
+                    minimum,
+                })
+                .into_response());
+            }
 
-        Ok(HtmlTemplate(LeaderboardTemplate { leaderboard }).into_response())
-    })
+            Ok(HtmlTemplate(LeaderboardTemplate {
+                leaderboard,
+                periods,
+                period,
+                minimum,
+            })
+            .into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn rating_history_page(
+    headers: HeaderMap,
+    State(db): State<Db>,
+    Path(email): Path<String>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        _token,
+        {
+            let history = db::get_rating_history(db, email.clone()).await?;
+
+            if !headers.contains_key("HX-Request") {
+                return Ok(
+                    HtmlTemplate(FullRatingHistoryTemplate { email, history }).into_response()
+                );
+            }
+
+            Ok(HtmlTemplate(RatingHistoryTemplate { email, history }).into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn api_tokens_page(
+    headers: HeaderMap,
+    State(db): State<Db>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let tokens = db::get_api_tokens(db, token.user).await?;
+
+            if !headers.contains_key("HX-Request") {
+                return Ok(HtmlTemplate(FullApiTokensTemplate {
+                    tokens,
+                    new_token: None,
+                })
+                .into_response());
+            }
+
+            Ok(HtmlTemplate(ApiTokensTemplate {
+                tokens,
+                new_token: None,
+            })
+            .into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ApiTokenForm {
+    name: String,
+}
+
+async fn create_api_token(
+    State(db): State<Db>,
+    jar: CookieJar,
+    Form(form): Form<ApiTokenForm>,
+) -> Result<HtmlTemplate<ApiTokensTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let name = form.name.trim();
+            if name.is_empty() || name.chars().count() > 64 {
+                return Err(AlertTemplate::bad_request("geef een naam van hoogstens 64 tekens"));
+            }
+
+            let (new_token, hash) = auth::create_api_token();
+            db::add_api_token(db.clone(), token.user.clone(), name.to_string(), hash).await?;
+
+            Ok(HtmlTemplate(ApiTokensTemplate {
+                tokens: db::get_api_tokens(db, token.user).await?,
+                new_token: Some(new_token),
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn revoke_api_token(
+    State(db): State<Db>,
+    Path(token_id): Path<i64>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<ApiTokensTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            db::remove_api_token(db.clone(), token.user.clone(), token_id).await?;
+
+            Ok(HtmlTemplate(ApiTokensTemplate {
+                tokens: db::get_api_tokens(db, token.user).await?,
+                new_token: None,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn invites_page(
+    headers: HeaderMap,
+    State(db): State<Db>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let invites = db::get_invites(db, token.user).await?;
+
+            if !headers.contains_key("HX-Request") {
+                return Ok(HtmlTemplate(FullInvitesTemplate { invites }).into_response());
+            }
+
+            Ok(HtmlTemplate(InvitesTemplate { invites }).into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn accept_invite(
+    State(db): State<Db>,
+    Path(invite_id): Path<i64>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<InvitesTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let game_id =
+                int_err!(db::accept_invite(db.clone(), token.user.clone(), invite_id).await)?;
+
+            // the settings of the game now show this seat as linked
+            let game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            let player_links = player_links(db.clone(), &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game, player_links));
+
+            rating::update(Change::Game(game_id.clone()));
+
+            Ok(HtmlTemplate(InvitesTemplate {
+                invites: db::get_invites(db, token.user).await?,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn decline_invite(
+    State(db): State<Db>,
+    Path(invite_id): Path<i64>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<InvitesTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            int_err!(db::decline_invite(db.clone(), token.user.clone(), invite_id).await)?;
+
+            Ok(HtmlTemplate(InvitesTemplate {
+                invites: db::get_invites(db, token.user).await?,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
 }
 
 async fn check_credentials(
@@ -355,116 +675,75 @@ async fn deal(
     Path(game_id): Path<String>,
     jar: CookieJar,
     body: String,
-) -> Result<impl IntoResponse, AlertTemplate> {
+) -> Result<Response, AlertTemplate> {
     auth!(
         jar,
         token,
         {
-            let body = urlencoding::decode(&body).unwrap().into_owned();
-            let parts = body.split('&').map(|part| {
-                let mut key_and_value = part.split('=');
-                (key_and_value.next().unwrap(), key_and_value.next().unwrap())
-            });
-
             let mut team = vec![];
             let mut opps = vec![];
-            let mut bid = Bid::GrandSlam;
-            let mut slagen = 13;
-
-            for (key, value) in parts {
+            let mut bid = None;
+            let mut slagen = None;
+            let mut made = vec![];
+            let mut ronde = None;
+            let mut versie = None;
+            let mut sleutel = None;
+
+            for part in body.split('&') {
+                let Some((key, value)) = part.split_once('=') else {
+                    return Err(AlertTemplate::bad_request("ongeldig formulier"));
+                };
+                let value = urlencoding::decode(&value.replace('+', " "))
+                    .map_err(|_| AlertTemplate::bad_request("ongeldig formulier"))?
+                    .into_owned();
                 match key {
-                    "team" => team.push(value.to_string()),
-                    "bid" => bid = value.into(),
-                    "slagen" => slagen = value.parse().unwrap(),
-                    "opp" => opps.push(value.to_string()),
-                    _ => unreachable!(),
+                    "team" => team.push(value),
+                    "bid" => {
+                        let parsed: Bid = value
+                            .parse()
+                            .map_err(|e: BidErr| AlertTemplate::bad_request(&e.to_string()))?;
+                        bid = Some(parsed);
+                    }
+                    "slagen" => slagen = value.parse::<i16>().ok(),
+                    "opp" => opps.push(value),
+                    "geslaagd" => made.push(value),
+                    "ronde" => {
+                        let parsed = value
+                            .parse::<usize>()
+                            .map_err(|_| AlertTemplate::bad_request("ongeldig formulier"))?;
+                        ronde = Some(parsed);
+                    }
+                    "versie" => versie = value.parse::<i64>().ok(),
+                    "sleutel" => sleutel = Some(value),
+                    _ => return Err(AlertTemplate::bad_request("ongeldig formulier")),
                 }
             }
 
-            let mut current_game = db::get_game(db.clone(), token.user.clone(), game_id.clone())
-                .await
-                .unwrap();
-
-            // convert team into a usize or (usize, usize)
-            let mut indexes: Vec<_> = team
-                .iter()
-                .map(|player| {
-                    current_game
-                        .players
-                        .clone()
-                        .into_iter()
-                        .position(|p| p.to_lowercase() == player.to_lowercase())
-                        .unwrap()
-                })
-                .collect();
+            let _submission = one_at_a_time(&game_id, sleutel.as_deref()).await;
+            let mut current_game =
+                db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
 
-            let other_indexes: Vec<_> = if opps.is_empty() {
-                (0..4)
-                    .filter(|i| !indexes.iter().any(|j| *i == *j))
-                    .collect()
-            } else {
-                opps.iter()
-                    .map(|player| {
-                        current_game
-                            .players
-                            .clone()
-                            .into_iter()
-                            .position(|p| p.to_lowercase() == player.to_lowercase())
-                            .unwrap()
-                    })
-                    .collect()
-            };
+            if let Some(response) = replay(db.clone(), &game_id, sleutel.as_deref()).await? {
+                return Ok(response);
+            }
 
-            let team = match indexes.len() {
-                1 => {
-                    if other_indexes.len() != 3 {
-                        return Err(AlertTemplate::bad_request("need three opponents"));
-                    }
-                    Team::Solo(
-                        indexes[0],
-                        (other_indexes[0], other_indexes[1], other_indexes[2]),
-                    )
-                }
-                2 => {
-                    if other_indexes.len() != 2 {
-                        return Err(AlertTemplate::bad_request("need two opponents"));
-                    }
-                    Team::Duo(
-                        (indexes[0], indexes[1]),
-                        (other_indexes[0], other_indexes[1]),
-                    )
-                }
-                _ => unreachable!(),
-            };
+            if current_game.changed_since(versie) {
+                return conflict(db, token.user, game_id).await;
+            }
 
-            current_game.add_deal(Deal {
-                team,
-                bid,
-                achieved: slagen,
-            });
-
-            let points = current_game.last_diff().unwrap();
-            let players = current_game.players.clone();
-            let scores = current_game.scores.last().unwrap().clone();
-
-            db::save_game(
-                db.clone(),
-                token.user.clone(),
-                game_id.clone(),
-                current_game,
-            )
-            .await
-            .map_err(|_| AlertTemplate::internal_server_error())?;
+            // without a round, this is a new deal
+            let round = ronde.unwrap_or(current_game.deals.len());
+            let deal = build_deal(&current_game, round, &team, &opps, &made, bid, slagen)?;
 
-            let db2 = db.clone();
-            tokio::spawn(async move { let _ = crate::rating::recompute_all(db2).await; });
+            let response = if round < current_game.deals.len() {
+                int_err!(current_game.replace_deal(round, deal))?;
+                store_game(db.clone(), token.user, game_id.clone(), current_game, round).await?
+            } else {
+                int_err!(current_game.add_deal(deal))?;
+                store_round(db.clone(), token.user, game_id.clone(), current_game).await?
+            };
 
-            Ok(HtmlTemplate(PointsTemplate {
-                id: game_id,
-                points,
-                players,
-                scores,
-            }))
+            remember(db, &game_id, sleutel, response).await
         },
         {
             Err(AlertTemplate {
@@ -475,64 +754,527 @@ async fn deal(
     )
 }
 
-async fn undo(
+/// Builds a deal for a round out of the names of the players that took part
+///
+/// Without opponents, everybody else that plays that round is against the team.
+/// For several misery players, `made` names the ones that got their misery.
+fn build_deal(
+    game: &Game,
+    round: usize,
+    team: &[String],
+    opps: &[String],
+    made: &[String],
+    bid: Option<Bid>,
+    slagen: Option<i16>,
+) -> Result<Deal, AlertTemplate> {
+    let bid = bid.ok_or(AlertTemplate::bad_request("geen bod gekozen"))?;
+    // several misery players have an outcome each instead of a trick count
+    let multi_misery = bid.is_misery() && team.len() > 1;
+    let slagen = match slagen.filter(|s| (0..=13).contains(s)) {
+        Some(slagen) => slagen,
+        None if multi_misery => 0,
+        None => return Err(AlertTemplate::bad_request("ongeldig aantal slagen")),
+    };
+
+    if round > game.deals.len() {
+        return Err(Error::NoRound.into_alert());
+    }
+
+    // convert team into a usize or (usize, usize)
+    let indexes = team
+        .iter()
+        .map(|player| game.players.position(player))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(AlertTemplate::bad_request("onbekende speler"))?;
+
+    if let Some(&out) = indexes.iter().find(|i| !game.is_active_at(i, &round)) {
+        let name = game.players[out].to_string();
+        return Err(Error::SittingOut(name).into_alert());
+    }
+
+    let other_indexes: Vec<_> = if opps.is_empty() {
+        game.active_at(round)
+            .into_iter()
+            .filter(|i| !indexes.contains(i))
+            .collect()
+    } else {
+        opps.iter()
+            .map(|player| game.players.position(player))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(AlertTemplate::bad_request("onbekende speler"))?
+    };
+
+    let team = match indexes.len() {
+        _ if multi_misery => {
+            if indexes.len() + other_indexes.len() != 4 {
+                return Err(AlertTemplate::bad_request("er moeten vier spelers meedoen"));
+            }
+            // a failed misery is kept as a single trick
+            let players = indexes
+                .iter()
+                .zip(team)
+                .map(|(&i, name)| {
+                    let got_it = made.iter().any(|m| m.to_lowercase() == name.to_lowercase());
+                    (i, i16::from(!got_it))
+                })
+                .collect();
+            Team::Misery(players, other_indexes)
+        }
+        1 => {
+            if other_indexes.len() != 3 {
+                return Err(AlertTemplate::bad_request("kies drie tegenspelers"));
+            }
+            Team::Solo(
+                indexes[0],
+                (other_indexes[0], other_indexes[1], other_indexes[2]),
+            )
+        }
+        2 => {
+            if other_indexes.len() != 2 {
+                return Err(AlertTemplate::bad_request("kies twee tegenspelers"));
+            }
+            Team::Duo(
+                (indexes[0], indexes[1]),
+                (other_indexes[0], other_indexes[1]),
+            )
+        }
+        _ => return Err(AlertTemplate::bad_request("kies één of twee spelers")),
+    };
+
+    Ok(Deal {
+        team,
+        bid,
+        achieved: slagen,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct RoundForm {
+    ronde: Option<usize>,
+    versie: Option<i64>,
+    sleutel: Option<String>,
+}
+
+async fn all_pass(
     State(db): State<Db>,
     Path(game_id): Path<String>,
     jar: CookieJar,
-) -> Result<impl IntoResponse, AlertTemplate> {
+    Form(form): Form<RoundForm>,
+) -> Result<Response, AlertTemplate> {
     auth!(
         jar,
         token,
         {
+            let _submission = one_at_a_time(&game_id, form.sleutel.as_deref()).await;
             let mut current_game =
                 db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
 
-            if current_game.undo_deal().is_none() {
-                return Err(AlertTemplate::bad_request(
-                    "Geen rondes om ongedaan te maken",
-                ));
+            if let Some(response) = replay(db.clone(), &game_id, form.sleutel.as_deref()).await? {
+                return Ok(response);
             }
 
-            db::save_game(
-                db.clone(),
-                token.user.clone(),
-                game_id.clone(),
-                current_game.clone(),
-            )
-            .await
-            .map_err(|_| AlertTemplate::internal_server_error())?;
+            if current_game.changed_since(form.versie) {
+                return conflict(db, token.user, game_id).await;
+            }
 
-            let db2 = db.clone();
-            tokio::spawn(async move { let _ = crate::rating::recompute_all(db2).await; });
+            let response = if let Some(round) = form.ronde {
+                int_err!(current_game.replace_round(round, Round::AllPass))?;
+                store_game(db.clone(), token.user, game_id.clone(), current_game, round).await?
+            } else {
+                int_err!(current_game.add_all_pass())?;
+                store_round(db.clone(), token.user, game_id.clone(), current_game).await?
+            };
 
-            Ok(HtmlTemplate(GameTemplate {
-                id: game_id,
-                game: current_game,
-                solobids: solo_bids(),
-                duobids: duo_bids(),
-            }))
+            remember(db, &game_id, form.sleutel, response).await
         },
-        {
-            Err(AlertTemplate {
-                code: StatusCode::UNAUTHORIZED,
-                alert: "Unauthorized".into(),
-            })
-        }
+        { Err(AlertTemplate::unauthorized()) }
     )
 }
 
-pub async fn new_game_form(
-    headers: HeaderMap,
+async fn delete_deal(
+    State(db): State<Db>,
+    Path((game_id, round)): Path<(String, usize)>,
     jar: CookieJar,
-) -> Result<impl IntoResponse, impl IntoResponse> {
-    auth!(jar, token, {
-        // full refresh needed
-        if !headers.contains_key("HX-Request") {
-            return Ok(HtmlTemplate(FullNewGameTemplate {}).into_response());
-        }
-        Ok(HtmlTemplate(NewGameTemplate {}).into_response())
-    })
-}
+    Query(form): Query<RenderedForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let mut current_game =
+                db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if current_game.changed_since(form.versie) {
+                return conflict(db, token.user, game_id).await;
+            }
+
+            int_err!(current_game.remove_deal(round))?;
+
+            store_game(db, token.user, game_id, current_game, round).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+pub async fn correction_form(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let game = db::get_game(db, token.user, game_id.clone()).await?;
+
+            Ok(HtmlTemplate(CorrectionFormTemplate {
+                id: game_id,
+                game,
+                max: MAX_CORRECTION,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn correction(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    body: String,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let mut reason = String::new();
+            let mut points = vec![];
+            let mut versie = None;
+
+            for part in body.split('&') {
+                let Some((key, value)) = part.split_once('=') else {
+                    return Err(AlertTemplate::bad_request("ongeldig formulier"));
+                };
+                let value = urlencoding::decode(&value.replace('+', " "))
+                    .map_err(|_| AlertTemplate::bad_request("ongeldig formulier"))?
+                    .into_owned();
+                match key {
+                    "reden" => reason = value.trim().to_string(),
+                    "punten" => points.push(
+                        value
+                            .trim()
+                            .parse::<i16>()
+                            .map_err(|_| AlertTemplate::bad_request("ongeldig aantal punten"))?,
+                    ),
+                    "versie" => versie = value.parse::<i64>().ok(),
+                    _ => return Err(AlertTemplate::bad_request("ongeldig formulier")),
+                }
+            }
+
+            if reason.is_empty() {
+                return Err(AlertTemplate::bad_request("geef een reden"));
+            }
+
+            let mut current_game =
+                db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if current_game.changed_since(versie) {
+                return conflict(db, token.user, game_id).await;
+            }
+
+            int_err!(current_game.add_correction(reason, points.as_slice().into()))?;
+
+            store_round(db, token.user, game_id, current_game).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+/// Saves a game whose history changed from round `from` on
+/// and shows the whole game again
+async fn store_game(
+    db: Db,
+    user: String,
+    game_id: String,
+    mut game: Game,
+    from: usize,
+) -> Result<Response, AlertTemplate> {
+    let saved = commit_rounds(db.clone(), user.clone(), &game_id, &mut game, from, Update::Game);
+    let role = match saved.await {
+        Err(Error::Conflict) => return conflict(db, user, game_id).await,
+        Err(e @ Error::NotAllowed(_)) => return Err(e.into_alert()),
+        result => result.map_err(|_| AlertTemplate::internal_server_error())?,
+    };
+
+    Ok((
+        [("HX-Retarget", "#content"), ("HX-Reswap", "innerHTML")],
+        HtmlTemplate(GameTemplate {
+            id: game_id,
+            round: game.deals.len(),
+            game,
+            role,
+            solobids: solo_bids(),
+            duobids: duo_bids(),
+            miserybids: misery_bids(),
+        }),
+    )
+        .into_response())
+}
+
+/// Saves a game that just got a new round and shows the points of that round
+async fn store_round(
+    db: Db,
+    user: String,
+    game_id: String,
+    mut game: Game,
+) -> Result<Response, AlertTemplate> {
+    let points = game.last_diff().unwrap();
+
+    // only the new round has to be written
+    let from = game.deals.len() - 1;
+    let saved = commit_rounds(db.clone(), user.clone(), &game_id, &mut game, from, Update::Points);
+    let role = match saved.await {
+        Err(Error::Conflict) => return conflict(db, user, game_id).await,
+        Err(e @ Error::NotAllowed(_)) => return Err(e.into_alert()),
+        result => result.map_err(|_| AlertTemplate::internal_server_error())?,
+    };
+
+    Ok(HtmlTemplate(PointsTemplate {
+        id: game_id,
+        points,
+        game,
+        role,
+    })
+    .into_response())
+}
+
+/// Saves the rounds of a game from `from` on, shows the change
+/// in every open view of the game and updates the ratings.
+/// Gives the role of the user that saved them.
+async fn commit_rounds(
+    db: Db,
+    user: String,
+    game_id: &str,
+    game: &mut Game,
+    from: usize,
+    update: fn(Game) -> Update,
+) -> Result<Role, Error> {
+    let role = db::save_rounds(db, user, game_id.to_string(), game, from).await?;
+
+    // the games before this one keep their ratings, whatever changed in it
+    rating::update(Change::Game(game_id.to_string()));
+    live::publish(game_id, update(game.clone()));
+
+    Ok(role)
+}
+
+/// The games that are handling a submission with a key right now
+static SUBMISSIONS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+
+/// Waits until no other submission with a key is being handled for the game.
+///
+/// Without this, a copy of a submission could get a conflict when the first one
+/// was saved but its response not remembered yet.
+async fn one_at_a_time(game_id: &str, key: Option<&str>) -> Option<OwnedMutexGuard<()>> {
+    key?;
+
+    let lock = {
+        let mut submissions = SUBMISSIONS.get_or_init(Default::default).lock().unwrap();
+        // forget the games that nobody submits to anymore
+        submissions.retain(|_, lock| Arc::strong_count(lock) > 1);
+        submissions.entry(game_id.to_string()).or_default().clone()
+    };
+    Some(lock.lock_owned().await)
+}
+
+/// The response that an earlier submission with the same key got
+async fn replay(
+    db: Db,
+    game_id: &str,
+    key: Option<&str>,
+) -> Result<Option<Response>, AlertTemplate> {
+    let Some(key) = key else {
+        return Ok(None);
+    };
+
+    let Some(stored) = db::get_response(db, game_id.to_string(), key).await? else {
+        return Ok(None);
+    };
+
+    let mut response = (
+        StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK),
+        stored.body,
+    )
+        .into_response();
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name),
+            HeaderValue::try_from(value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    Ok(Some(response))
+}
+
+/// Keeps a successful response under the key of the form that caused it,
+/// so that a repeated submission of that form gets the same response
+async fn remember(
+    db: Db,
+    game_id: &str,
+    key: Option<String>,
+    response: Response,
+) -> Result<Response, AlertTemplate> {
+    let Some(key) = key else {
+        return Ok(response);
+    };
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|_| AlertTemplate::internal_server_error())?;
+
+    let stored = db::StoredResponse {
+        status: parts.status.as_u16(),
+        headers: parts
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+    db::save_response(db, game_id.to_string(), &key, &stored).await?;
+
+    Ok(Response::from_parts(parts, body.into()))
+}
+
+/// Shows the game as it is now, when somebody else changed it
+/// between loading and saving it in this request
+async fn conflict(db: Db, user: String, game_id: String) -> Result<Response, AlertTemplate> {
+    let game = db::get_game(db.clone(), user.clone(), game_id.clone()).await?;
+    let role = db::get_role(db, user, game_id.clone()).await?;
+
+    Ok((
+        StatusCode::CONFLICT,
+        [("HX-Retarget", "#content"), ("HX-Reswap", "innerHTML")],
+        HtmlTemplate(ConflictTemplate {
+            alert: Error::Conflict.to_string(),
+            id: game_id,
+            round: game.deals.len(),
+            game,
+            role,
+            solobids: solo_bids(),
+            duobids: duo_bids(),
+            miserybids: misery_bids(),
+        }),
+    )
+        .into_response())
+}
+
+/// What a button carries over from when it was rendered
+#[derive(Deserialize)]
+pub struct RenderedForm {
+    versie: Option<i64>,
+    sleutel: Option<String>,
+}
+
+async fn undo(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<RenderedForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let _submission = one_at_a_time(&game_id, form.sleutel.as_deref()).await;
+            let mut current_game =
+                db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if let Some(response) = replay(db.clone(), &game_id, form.sleutel.as_deref()).await? {
+                return Ok(response);
+            }
+
+            if current_game.changed_since(form.versie) {
+                return conflict(db, token.user, game_id).await;
+            }
+
+            if current_game.finished {
+                return Err(Error::GameFinished.into_alert());
+            }
+
+            if current_game.undo_deal().is_none() {
+                return Err(AlertTemplate::bad_request(
+                    "Geen rondes om ongedaan te maken",
+                ));
+            }
+
+            // only the undone round has to go
+            let from = current_game.deals.len();
+            let response = store_game(db.clone(), token.user, game_id.clone(), current_game, from);
+            let response = response.await?;
+
+            remember(db, &game_id, form.sleutel, response).await
+        },
+        {
+            Err(AlertTemplate {
+                code: StatusCode::UNAUTHORIZED,
+                alert: "Unauthorized".into(),
+            })
+        }
+    )
+}
+
+async fn redo(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<RenderedForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let mut current_game =
+                db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if current_game.changed_since(form.versie) {
+                return conflict(db, token.user, game_id).await;
+            }
+
+            if current_game.finished {
+                return Err(Error::GameFinished.into_alert());
+            }
+
+            // only the redone round has to be written
+            let from = current_game.deals.len();
+            if current_game.redo_deal().is_none() {
+                return Err(AlertTemplate::bad_request("Geen rondes om opnieuw te doen"));
+            }
+
+            store_game(db, token.user, game_id, current_game, from).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+pub async fn new_game_form(
+    headers: HeaderMap,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    auth!(jar, token, {
+        // full refresh needed
+        if !headers.contains_key("HX-Request") {
+            return Ok(HtmlTemplate(FullNewGameTemplate {}).into_response());
+        }
+        Ok(HtmlTemplate(NewGameTemplate {}).into_response())
+    })
+}
 
 #[derive(Deserialize, Validate)]
 pub struct NewGameForm {
@@ -564,6 +1306,9 @@ pub struct NewGameForm {
     id6: String,
     #[garde(alphanumeric)]
     id7: String,
+    #[garde(skip)]
+    #[serde(default)]
+    regels: Option<String>,
 }
 
 pub async fn new_game(
@@ -576,6 +1321,10 @@ pub async fn new_game(
         token,
         {
             let form = form.into_inner();
+            let rules = match form.regels.as_deref() {
+                None | Some("") => RuleSet::default(),
+                Some(rules) => int_err!(rules.parse::<RuleSet>())?,
+            };
 
             let owner = token.user;
             let mut players: Players = [&form.player1, &form.player2, &form.player3, &form.player4]
@@ -587,42 +1336,31 @@ pub async fn new_game(
             players.opt_add_player(&form.player6);
             players.opt_add_player(&form.player7);
 
-            let (id, game) = db::start_game(db.clone(), form.name, players)
-                .await
-                .map_err(|e| e.into_alert())?;
-
-            // add all given logins as players of this game
-            // first: me myself and I
-            let my_id = db::get_user_id(db.clone(), owner)
-                .await
-                .map_err(|e| e.into_alert())?;
-
-            int_err!(db::add_player(db.clone(), id.clone(), my_id, form.player1).await)?;
+            // the others only join when they accept their invite
+            let invites = [
+                (form.id2, form.player2),
+                (form.id3, form.player3),
+                (form.id4, form.player4),
+                (form.id5, form.player5),
+                (form.id6, form.player6),
+                (form.id7, form.player7),
+            ]
+            .into_iter()
+            .filter(|(user_id, _)| !user_id.is_empty())
+            .collect();
 
-            if !form.id2.is_empty() {
-                int_err!(db::add_player(db.clone(), id.clone(), form.id2, form.player2).await)?;
-            }
-            if !form.id3.is_empty() {
-                int_err!(db::add_player(db.clone(), id.clone(), form.id3, form.player3).await)?;
-            }
-            if !form.id4.is_empty() {
-                int_err!(db::add_player(db.clone(), id.clone(), form.id4, form.player4).await)?;
-            }
-            if !form.id5.is_empty() {
-                int_err!(db::add_player(db.clone(), id.clone(), form.id5, form.player5).await)?;
-            }
-            if !form.id6.is_empty() {
-                int_err!(db::add_player(db.clone(), id.clone(), form.id6, form.player6).await)?;
-            }
-            if !form.id7.is_empty() {
-                int_err!(db::add_player(db.clone(), id.clone(), form.id7, form.player7).await)?;
-            }
+            // me myself and I play the first player and own the game
+            let started = db::start_game(db.clone(), owner, form.name, players, rules, invites);
+            let (id, game) = int_err!(started.await)?;
 
             Ok(HtmlTemplate(GameTemplate {
                 id,
+                round: game.deals.len(),
                 game,
+                role: Role::Owner,
                 solobids: solo_bids(),
                 duobids: duo_bids(),
+                miserybids: misery_bids(),
             }))
         },
         {
@@ -638,17 +1376,52 @@ pub async fn deal_form(
     State(db): State<Db>,
     Path(game_id): Path<String>,
     jar: CookieJar,
-) -> Result<impl IntoResponse, impl IntoResponse> {
-    auth!(jar, token, {
-        let game = db::get_game(db, token.user, game_id.clone()).await.unwrap();
+) -> Result<impl IntoResponse, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let game = db::get_game(db, token.user, game_id.clone()).await?;
 
-        Ok(HtmlTemplate(DealFormTemplate {
-            id: game_id,
-            game,
-            solobids: solo_bids(),
-            duobids: duo_bids(),
-        }))
-    })
+            Ok(HtmlTemplate(DealFormTemplate {
+                id: game_id,
+                round: game.deals.len(),
+                game,
+                solobids: solo_bids(),
+                duobids: duo_bids(),
+                miserybids: misery_bids(),
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+pub async fn edit_deal_form(
+    State(db): State<Db>,
+    Path((game_id, round)): Path<(String, usize)>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let game = db::get_game(db, token.user, game_id.clone()).await?;
+
+            if round >= game.deals.len() {
+                return Err(Error::NoRound.into_alert());
+            }
+
+            Ok(HtmlTemplate(DealFormTemplate {
+                id: game_id,
+                round,
+                game,
+                solobids: solo_bids(),
+                duobids: duo_bids(),
+                miserybids: misery_bids(),
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
 }
 
 pub async fn games(
@@ -677,7 +1450,10 @@ pub async fn game(
     jar: CookieJar,
 ) -> Result<Response, impl IntoResponse> {
     auth!(jar, token, {
-        let game = db::get_game_by_id(db, token.user, game_id.clone())
+        let game = db::get_game_by_id(db.clone(), token.user.clone(), game_id.clone())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let role = db::get_role(db, token.user, game_id.clone())
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -685,18 +1461,24 @@ pub async fn game(
         if !headers.contains_key("HX-Request") {
             return Ok(HtmlTemplate(FullGameTemplate {
                 id: game_id,
+                round: game.deals.len(),
                 game,
+                role,
                 solobids: solo_bids(),
                 duobids: duo_bids(),
+                miserybids: misery_bids(),
             })
             .into_response());
         }
 
         Ok(HtmlTemplate(GameTemplate {
             id: game_id,
+            round: game.deals.len(),
             game,
+            role,
             solobids: solo_bids(),
             duobids: duo_bids(),
+            miserybids: misery_bids(),
         })
         .into_response())
     })
@@ -706,31 +1488,77 @@ pub async fn delete_game(
     State(db): State<Db>,
     Path(game_id): Path<String>,
     jar: CookieJar,
-) -> Result<impl IntoResponse, impl IntoResponse> {
-    auth!(jar, token, {
-        let my_id = db::get_user_id(db.clone(), token.user.clone())
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        db::remove_player(db.clone(), game_id.clone(), my_id)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        Ok(StatusCode::OK)
-    })
-}
-
-#[derive(Deserialize)]
-pub struct Email {
-    email: String,
+) -> Result<StatusCode, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            // the game only goes away for good once it has been in the trash for a while
+            int_err!(db::trash_game(db, token.user, game_id.clone()).await)?;
+            // the ratings no longer count the game, or the player that left it
+            rating::update(Change::Game(game_id));
+            Ok(StatusCode::OK)
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
 }
 
-pub async fn check_email(
+async fn trash_page(
+    headers: HeaderMap,
     State(db): State<Db>,
-    Form(email): Form<Email>,
-) -> Result<HtmlTemplate<LoginActions>, AlertTemplate> {
-    Ok(HtmlTemplate(LoginActions {
-        exists: db::email_exists(db, email.email).await?,
-    }))
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let games = db::get_trash(db, token.user, TRASH_WINDOW).await?;
+
+            if !headers.contains_key("HX-Request") {
+                return Ok(HtmlTemplate(FullTrashTemplate { games }).into_response());
+            }
+
+            Ok(HtmlTemplate(TrashTemplate { games }).into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+async fn restore_game(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<TrashTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            int_err!(
+                db::restore_game(db.clone(), token.user.clone(), game_id.clone(), TRASH_WINDOW)
+                    .await
+            )?;
+            rating::update(Change::Game(game_id));
+
+            Ok(HtmlTemplate(TrashTemplate {
+                games: db::get_trash(db, token.user, TRASH_WINDOW).await?,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+#[derive(Deserialize)]
+pub struct Email {
+    email: String,
+}
+
+pub async fn check_email(
+    State(db): State<Db>,
+    Form(email): Form<Email>,
+) -> Result<HtmlTemplate<LoginActions>, AlertTemplate> {
+    Ok(HtmlTemplate(LoginActions {
+        exists: db::email_exists(db, email.email).await?,
+    }))
 }
 
 pub async fn user_qr(
@@ -759,6 +1587,31 @@ pub async fn user_qr(
     )
 }
 
+/// The players of a game, with their link status and who deals next
+async fn player_links(db: Db, game_id: &str, game: &Game) -> Result<Vec<PlayerLinkStatus>, Error> {
+    let linked_players = db::get_game_players(db.clone(), game_id.to_string()).await?;
+    let invited = db::get_invited_aliases(db, game_id.to_string()).await?;
+
+    let dealer = game.dealer();
+    Ok((&game.players)
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let role = linked_players
+                .iter()
+                .find(|lp| lp.alias == *name)
+                .map(|lp| lp.role);
+            PlayerLinkStatus {
+                name: name.clone(),
+                linked: role.is_some(),
+                role,
+                invited: invited.contains(name),
+                dealer: i == dealer,
+            }
+        })
+        .collect())
+}
+
 pub async fn game_settings(
     headers: HeaderMap,
     State(db): State<Db>,
@@ -770,35 +1623,28 @@ pub async fn game_settings(
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        let linked_players = db::get_game_players(db.clone(), game_id.clone())
+        let player_links = player_links(db.clone(), &game_id, &game)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let role = db::get_role(db, token.user, game_id.clone())
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        let player_links: Vec<PlayerLinkStatus> = game
-            .players
-            .into_iter()
-            .map(|name| {
-                let linked = linked_players.iter().any(|lp| lp.alias == *name);
-                PlayerLinkStatus {
-                    name: name.clone(),
-                    linked,
-                }
-            })
-            .collect();
 
         if !headers.contains_key("HX-Request") {
             return Ok(HtmlTemplate(FullGameSettingsTemplate {
                 id: game_id,
-                game_name: game.name,
+                game,
                 player_links,
+                role,
             })
             .into_response());
         }
 
         Ok(HtmlTemplate(GameSettingsTemplate {
             id: game_id,
-            game_name: game.name,
+            game,
             player_links,
+            role,
         })
         .into_response())
     })
@@ -810,7 +1656,8 @@ pub struct LinkPlayerForm {
     player_name: String,
 }
 
-pub async fn link_player(
+/// Invites the account of a scanned login id for a seat of the game
+pub async fn invite_player(
     State(db): State<Db>,
     Path(game_id): Path<String>,
     jar: CookieJar,
@@ -820,36 +1667,70 @@ pub async fn link_player(
         jar,
         token,
         {
-            db::add_player(db.clone(), game_id.clone(), form.user_id, form.player_name)
+            let game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            int_err!(
+                db::invite_player(
+                    db.clone(),
+                    token.user,
+                    game_id.clone(),
+                    form.user_id,
+                    form.player_name
+                )
                 .await
-                .map_err(|_| AlertTemplate::internal_server_error())?;
+            )?;
 
-            let db2 = db.clone();
-            tokio::spawn(async move { let _ = crate::rating::recompute_all(db2).await; });
+            let player_links = player_links(db, &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game.clone(), player_links.clone()));
 
-            let game = db::get_game_by_id(db.clone(), token.user.clone(), game_id.clone())
-                .await
-                .map_err(|_| AlertTemplate::internal_server_error())?;
+            Ok(HtmlTemplate(GameSettingsTemplate {
+                id: game_id,
+                game,
+                player_links,
+                role: Role::Owner,
+            })
+            .into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+#[derive(Deserialize)]
+pub struct RoleForm {
+    speler: String,
+    rol: Role,
+}
 
-            let linked_players = db::get_game_players(db.clone(), game_id.clone())
+/// Gives the account linked to a player another role in the game
+pub async fn set_role(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<RoleForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            int_err!(
+                db::set_role(
+                    db.clone(),
+                    token.user.clone(),
+                    game_id.clone(),
+                    form.speler,
+                    form.rol
+                )
                 .await
-                .map_err(|_| AlertTemplate::internal_server_error())?;
+            )?;
 
-            let player_links: Vec<PlayerLinkStatus> = game
-                .players
-                .into_iter()
-                .map(|name| {
-                    let linked = linked_players.iter().any(|lp| lp.alias == *name);
-                    PlayerLinkStatus {
-                        name: name.clone(),
-                        linked,
-                    }
-                })
-                .collect();
+            let game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            let player_links = player_links(db.clone(), &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game.clone(), player_links.clone()));
 
             Ok(HtmlTemplate(GameSettingsTemplate {
+                role: db::get_role(db, token.user, game_id.clone()).await?,
                 id: game_id,
-                game_name: game.name,
+                game,
                 player_links,
             })
             .into_response())
@@ -858,6 +1739,143 @@ pub async fn link_player(
     )
 }
 
+#[derive(Deserialize)]
+pub struct DealerForm {
+    player: usize,
+}
+
+pub async fn set_dealer(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<DealerForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let mut game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if form.player >= game.players.len() {
+                return Err(AlertTemplate::bad_request("onbekende speler"));
+            }
+            game.set_dealer(form.player);
+
+            store_settings(db, token.user, game_id, game).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+#[derive(Deserialize)]
+pub struct EndForm {
+    einde: String,
+    waarde: Option<String>,
+}
+
+pub async fn set_end(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<EndForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let value = form.waarde.as_deref().map(str::trim).unwrap_or_default();
+            let end = match form.einde.as_str() {
+                "geen" => None,
+                "rondes" => Some(EndCondition::Rounds(
+                    value
+                        .parse()
+                        .map_err(|_| AlertTemplate::bad_request("ongeldig aantal rondes"))?,
+                )),
+                "punten" => Some(EndCondition::Score(
+                    value
+                        .parse()
+                        .map_err(|_| AlertTemplate::bad_request("ongeldig aantal punten"))?,
+                )),
+                _ => return Err(AlertTemplate::bad_request("ongeldig formulier")),
+            };
+
+            let mut game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            game.set_end(end);
+
+            store_settings(db, token.user, game_id, game).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+pub async fn finish_game(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let mut game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            game.finish();
+
+            store_settings(db, token.user, game_id, game).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+pub async fn reopen_game(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let mut game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            game.reopen();
+
+            store_settings(db, token.user, game_id, game).await
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+/// Saves a game whose settings changed and shows the settings again
+async fn store_settings(
+    db: Db,
+    user: String,
+    game_id: String,
+    mut game: Game,
+) -> Result<Response, AlertTemplate> {
+    let rounds = game.deals.len();
+    let saved = db::save_rounds(db.clone(), user.clone(), game_id.clone(), &mut game, rounds);
+    let role = match saved.await {
+        Err(Error::Conflict) => return conflict(db, user, game_id).await,
+        Err(e @ Error::NotAllowed(_)) => return Err(e.into_alert()),
+        result => result.map_err(|_| AlertTemplate::internal_server_error())?,
+    };
+
+    rating::update(Change::Game(game_id.clone()));
+
+    let player_links = player_links(db.clone(), &game_id, &game)
+        .await
+        .map_err(|_| AlertTemplate::internal_server_error())?;
+    live::publish(&game_id, Update::Game(game.clone()));
+    live::publish(&game_id, Update::Settings(game.clone(), player_links.clone()));
+
+    Ok(HtmlTemplate(GameSettingsTemplate {
+        role,
+        id: game_id,
+        game,
+        player_links,
+    })
+    .into_response())
+}
+
 pub async fn chart(
     State(db): State<Db>,
     Path(game_id): Path<String>,
@@ -868,24 +1886,446 @@ pub async fn chart(
         token,
         {
             let game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            let scores = chart_scores(&game);
 
-            let mut scores = vec![];
+            Ok(HtmlTemplate(Chart { game_id, scores }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
 
-            for player in &game.players {
-                scores.push(ChartScore {
-                    name: player.clone(),
-                    scores: vec![],
-                });
+/// The scores of every player over the course of a game
+fn chart_scores(game: &Game) -> Vec<ChartScore> {
+    let mut scores = vec![];
+
+    for player in &game.players {
+        scores.push(ChartScore {
+            name: player.clone(),
+            scores: vec![],
+        });
+    }
+
+    for points in &game.scores {
+        for (player, point) in points.0.iter().enumerate() {
+            scores[player].scores.push(*point);
+        }
+    }
+
+    scores
+}
+
+/// The chart of a shared game, which is part of its page
+fn shared_chart(game_id: &str, game: &Game) -> String {
+    Chart {
+        game_id: game_id.to_string(),
+        scores: chart_scores(game),
+    }
+    .render()
+    .unwrap_or_default()
+}
+
+/// The full share link of a game, for as long as it is shared
+fn share_link(game_id: &str, nonce: Option<String>) -> Result<Option<String>, AlertTemplate> {
+    let Some(nonce) = nonce else {
+        return Ok(None);
+    };
+    let signature = auth::sign_share_link(game_id, &nonce)?;
+    let domain = crate::config_fn("DOMAIN")?;
+
+    Ok(Some(format!("{domain}/share/{game_id}/{signature}")))
+}
+
+pub async fn share_section(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<ShareTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let nonce = db::get_share_nonce(db, token.user, game_id.clone()).await?;
+            let link = share_link(&game_id, nonce)?;
+
+            Ok(HtmlTemplate(ShareTemplate { id: game_id, link }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+/// Shares a game under a new link, the old link stops working
+pub async fn share_game(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<ShareTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let nonce = auth::create_share_nonce();
+            db::set_share_nonce(db, token.user, game_id.clone(), Some(nonce.clone())).await?;
+            let link = share_link(&game_id, Some(nonce))?;
+
+            Ok(HtmlTemplate(ShareTemplate { id: game_id, link }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+pub async fn unshare_game(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<ShareTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            db::set_share_nonce(db, token.user, game_id.clone(), None).await?;
+
+            Ok(HtmlTemplate(ShareTemplate {
+                id: game_id,
+                link: None,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+/// How long a join code works, in seconds
+const JOIN_CODE_LIFETIME: i64 = 15 * 60;
+
+/// The join code of a game with the QR of its link, for as long as it works
+fn active_join_code(join_code: Option<(String, i64)>) -> Result<Option<ActiveJoinCode>, Error> {
+    let Some((code, seconds_left)) = join_code else {
+        return Ok(None);
+    };
+    let domain = crate::config_fn("DOMAIN")?;
+
+    let svg = qrcode_generator::to_svg_to_string(
+        format!("{domain}/join?code={code}"),
+        qrcode_generator::QrCodeEcc::Low,
+        256,
+        None::<&str>,
+    )
+    .unwrap();
+
+    Ok(Some(ActiveJoinCode {
+        code,
+        svg,
+        minutes_left: (seconds_left + 59) / 60,
+    }))
+}
+
+pub async fn join_code_section(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<JoinCodeTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let join_code = db::get_join_code(db, token.user, game_id.clone()).await?;
+
+            Ok(HtmlTemplate(JoinCodeTemplate {
+                id: game_id,
+                join_code: active_join_code(join_code)?,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+/// Makes a new join code for a game, the old one stops working
+pub async fn create_join_code(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<HtmlTemplate<JoinCodeTemplate>, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let code = auth::create_join_code();
+            db::set_join_code(db, token.user, game_id.clone(), &code, JOIN_CODE_LIFETIME).await?;
+
+            Ok(HtmlTemplate(JoinCodeTemplate {
+                id: game_id,
+                join_code: active_join_code(Some((code, JOIN_CODE_LIFETIME)))?,
+            }))
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+#[derive(Deserialize)]
+struct JoinQuery {
+    #[serde(default)]
+    code: String,
+}
+
+/// Asks for a join code, and once it has a working one, for the seat to take
+async fn join_page(
+    headers: HeaderMap,
+    State(db): State<Db>,
+    Query(query): Query<JoinQuery>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    let full = !headers.contains_key("HX-Request");
+
+    auth!(
+        jar,
+        _token,
+        {
+            let code = query.code.trim().to_uppercase();
+            let mut page = JoinTemplate {
+                code,
+                game_name: None,
+                seats: vec![],
+                error: None,
+            };
+
+            if !page.code.is_empty() {
+                match db::get_join_game(db.clone(), &page.code).await {
+                    Ok((game_id, game)) => {
+                        let linked = db::get_game_players(db, game_id).await?;
+                        page.game_name = Some(game.name);
+                        page.seats = game
+                            .players
+                            .into_iter()
+                            .filter(|player| !linked.iter().any(|l| &l.alias == player))
+                            .collect();
+                    }
+                    Err(Error::NoJoinCode) => page.error = Some(Error::NoJoinCode.to_string()),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            if full {
+                return Ok(HtmlTemplate(FullJoinTemplate {
+                    code: page.code,
+                    game_name: page.game_name,
+                    seats: page.seats,
+                    error: page.error,
+                })
+                .into_response());
+            }
+
+            Ok(HtmlTemplate(page).into_response())
+        },
+        {
+            // whoever scanned the code without being logged in, gets to log in first
+            if full {
+                return Ok(HtmlTemplate(IndexTemplate {}).into_response());
             }
+            Err(AlertTemplate::unauthorized())
+        }
+    )
+}
+
+#[derive(Deserialize)]
+struct JoinForm {
+    code: String,
+    speler: String,
+}
+
+/// Takes a seat with a join code and goes to the game
+async fn join_game(
+    State(db): State<Db>,
+    jar: CookieJar,
+    Form(form): Form<JoinForm>,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            let code = form.code.trim().to_uppercase();
+            let game_id = int_err!(
+                db::join_with_code(db.clone(), token.user.clone(), &code, form.speler).await
+            )?;
 
-            for points in game.scores {
-                for (player, point) in points.0.iter().enumerate() {
-                    scores[player].scores.push(*point);
+            // the settings of the game now show this seat as linked
+            let game = db::get_game(db.clone(), token.user, game_id.clone()).await?;
+            let player_links = player_links(db.clone(), &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game, player_links));
+
+            rating::update(Change::Game(game_id.clone()));
+
+            Ok([("HX-Redirect", format!("/game/{game_id}"))].into_response())
+        },
+        { Err(AlertTemplate::unauthorized()) }
+    )
+}
+
+/// A shared game, read-only and without login
+pub async fn shared_game(
+    headers: HeaderMap,
+    State(db): State<Db>,
+    Path((game_id, signature)): Path<(String, String)>,
+) -> Result<Response, AlertTemplate> {
+    let game = db::get_shared_game(db, game_id.clone(), &signature)
+        .await
+        .map_err(|_| AlertTemplate {
+            code: StatusCode::NOT_FOUND,
+            alert: "deze link werkt niet (meer)".into(),
+        })?;
+    let chart = shared_chart(&game_id, &game);
+
+    if !headers.contains_key("HX-Request") {
+        return Ok(HtmlTemplate(FullSharedGameTemplate {
+            game_id,
+            signature,
+            game,
+            chart,
+        })
+        .into_response());
+    }
+
+    Ok(HtmlTemplate(SharedGameTemplate {
+        game_id,
+        signature,
+        game,
+        chart,
+    })
+    .into_response())
+}
+
+/// Streams the changes of a shared game, for as long as its link works
+pub async fn shared_live_updates(
+    State(db): State<Db>,
+    Path((game_id, signature)): Path<(String, String)>,
+) -> Result<Response, StatusCode> {
+    db::get_shared_game(db.clone(), game_id.clone(), &signature)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let updates = live::subscribe(&game_id);
+    let events = futures_util::stream::unfold(updates, move |mut updates| {
+        let db = db.clone();
+        let game_id = game_id.clone();
+        let signature = signature.clone();
+        async move {
+            loop {
+                match updates.recv().await {
+                    Ok(Update::Points(_) | Update::Game(_)) => break,
+                    Ok(Update::Settings(..)) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
                 }
             }
 
-            Ok(HtmlTemplate(Chart { game_id, scores }))
+            // the stream ends as soon as the link no longer works
+            let game = db::get_shared_game(db, game_id.clone(), &signature)
+                .await
+                .ok()?;
+            let html = SharedGameTemplate {
+                chart: shared_chart(&game_id, &game),
+                game_id,
+                signature,
+                game,
+            }
+            .render()
+            .unwrap_or_default();
+            let event = Event::default().event("game").data(html);
+            Some((Ok::<_, Infallible>(event), updates))
+        }
+    });
+
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// Streams the changes of a game to one open view of it
+pub async fn live_updates(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Response, AlertTemplate> {
+    auth!(
+        jar,
+        token,
+        {
+            // only the players of a game get to watch it
+            db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            Ok(Sse::new(live_events(db, token.user, game_id))
+                .keep_alive(KeepAlive::default())
+                .into_response())
         },
         { Err(AlertTemplate::unauthorized()) }
     )
 }
+
+/// Renders every update of a game as a server-sent event, for one view
+///
+/// Each view renders the game on its own, so that its forms get their own keys
+/// and its settings show what the role of its user allows.
+fn live_events(
+    db: Db,
+    user: String,
+    game_id: String,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    futures_util::stream::unfold(live::subscribe(&game_id), move |mut updates| {
+        let db = db.clone();
+        let user = user.clone();
+        let id = game_id.clone();
+        async move {
+            let update = match updates.recv().await {
+                Ok(update) => update,
+                // a view that fell behind missed some points, so it gets the
+                // whole game again
+                Err(RecvError::Lagged(_)) => {
+                    let game = db::get_game(db.clone(), user.clone(), id.clone());
+                    Update::Game(game.await.ok()?)
+                }
+                Err(RecvError::Closed) => return None,
+            };
+
+            // the stream ends for whoever no longer plays in the game
+            let role = db::get_role(db, user, id.clone()).await.ok()?;
+
+            let (name, html) = match update {
+                Update::Points(game) => (
+                    "points",
+                    PointsTemplate {
+                        id,
+                        points: game.last_diff().unwrap(),
+                        game,
+                        role,
+                    }
+                    .render(),
+                ),
+                Update::Game(game) => (
+                    "game",
+                    GameTemplate {
+                        id,
+                        round: game.deals.len(),
+                        game,
+                        role,
+                        solobids: solo_bids(),
+                        duobids: duo_bids(),
+                        miserybids: misery_bids(),
+                    }
+                    .render(),
+                ),
+                Update::Settings(game, player_links) => (
+                    "settings",
+                    GameSettingsTemplate {
+                        role,
+                        id,
+                        game,
+                        player_links,
+                    }
+                    .render(),
+                ),
+            };
+
+            let event = Event::default()
+                .event(name)
+                .data(html.unwrap_or_default());
+            Some((Ok(event), updates))
+        }
+    })
+}
@@ -0,0 +1,548 @@
+//! The JSON API under `/api/v1`, for scripts and other clients than the browser
+//!
+//! It works with an API token as well as with the token cookie of the rest of the
+//! app, and is described in `openapi.json`, which is served at `/api/v1/openapi.json`.
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, Router};
+use axum::Json;
+use axum_extra::extract::cookie::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use super::{build_deal, commit_rounds, one_at_a_time, player_links, remember, replay};
+use crate::auth;
+use crate::db;
+use crate::error::{BidErr, Error};
+use crate::live::{self, Update};
+use crate::rating::{self, Change};
+use crate::template::{
+    AlertTemplate, Invite, LeaderboardEntry, PlayerLinkStatus, RatingChange, Role,
+};
+use crate::whist::{Bid, Game, Players, Points};
+use crate::Db;
+
+const OPENAPI: &str = include_str!("openapi.json");
+
+pub fn router() -> Router<Db> {
+    Router::new()
+        .route("/games", get(list_games))
+        .route("/games/:game_id", get(get_game))
+        .route("/games/:game_id/deals", post(post_deal))
+        .route("/games/:game_id/undo", post(undo))
+        .route("/games/:game_id/players", get(players).post(invite_player))
+        .route("/games/:game_id/roles", post(set_role))
+        .route("/join", post(join_game))
+        .route("/invites", get(invites))
+        .route("/invites/:invite_id/accept", post(accept_invite))
+        .route("/invites/:invite_id/decline", post(decline_invite))
+        .route("/ratings", get(ratings))
+        .route("/ratings/:email/history", get(rating_history))
+        .route("/openapi.json", get(openapi))
+}
+
+/// An error of the API, sent as `{"error": "..."}`
+pub struct ApiError {
+    code: StatusCode,
+    error: String,
+}
+
+impl ApiError {
+    fn unauthorized() -> Self {
+        ApiError {
+            code: StatusCode::UNAUTHORIZED,
+            error: "Unauthorized".into(),
+        }
+    }
+
+    fn bad_request(message: &str) -> Self {
+        ApiError {
+            code: StatusCode::BAD_REQUEST,
+            error: message.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Body {
+            error: String,
+        }
+
+        (self.code, Json(Body { error: self.error })).into_response()
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(value: Error) -> Self {
+        let code = match value {
+            Error::NoGameError | Error::NoRound | Error::NoInvite | Error::NoJoinCode => {
+                StatusCode::NOT_FOUND
+            }
+            Error::NotAllowed(_) => StatusCode::FORBIDDEN,
+            Error::Conflict
+            | Error::GameFinished
+            | Error::SeatTaken(_)
+            | Error::AlreadyPlaying
+            | Error::LastOwner => StatusCode::CONFLICT,
+            Error::SqlxError(_) => {
+                log::error!("api: {value}");
+                return ApiError {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    error: StatusCode::INTERNAL_SERVER_ERROR.to_string(),
+                };
+            }
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        ApiError {
+            code,
+            error: value.to_string(),
+        }
+    }
+}
+
+/// The helpers shared with the htmx routes report with an alert
+impl From<AlertTemplate> for ApiError {
+    fn from(value: AlertTemplate) -> Self {
+        ApiError {
+            code: value.code,
+            error: value.alert,
+        }
+    }
+}
+
+/// A game in the list of games
+#[derive(Serialize)]
+struct GameSummary {
+    id: String,
+    name: String,
+    players: Players,
+    scores: Points,
+    rounds: usize,
+    finished: bool,
+    version: i64,
+}
+
+/// A whole game, with all its rounds and scores
+#[derive(Serialize)]
+struct GameBody {
+    id: String,
+    #[serde(flatten)]
+    game: Game,
+}
+
+async fn list_games(
+    State(db): State<Db>,
+    jar: CookieJar,
+) -> Result<Json<Vec<GameSummary>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let games = db::get_games_with_ids(db, token.user).await?;
+
+            Ok(Json(
+                games
+                    .into_iter()
+                    .map(|id_game| GameSummary {
+                        id: id_game.id,
+                        name: id_game.game.name.clone(),
+                        scores: id_game.game.last_score().clone(),
+                        rounds: id_game.game.deals.len(),
+                        finished: id_game.game.finished,
+                        version: id_game.game.version,
+                        players: id_game.game.players,
+                    })
+                    .collect(),
+            ))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+async fn get_game(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Json<GameBody>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let game = db::get_game(db, token.user, game_id.clone()).await?;
+
+            Ok(Json(GameBody { id: game_id, game }))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// A deal, with the players by name
+#[derive(Deserialize)]
+struct DealRequest {
+    team: Vec<String>,
+    bid: String,
+    /// not needed when several players each play a misery
+    tricks: Option<i16>,
+    /// everybody else that plays the round, when left out
+    #[serde(default)]
+    opponents: Vec<String>,
+    /// the misery players that got their misery
+    #[serde(default)]
+    made: Vec<String>,
+    /// the round to replace, a new round when left out
+    round: Option<usize>,
+    /// the version of the game this deal was based on
+    version: Option<i64>,
+}
+
+/// Adds or replaces a deal and answers with the whole game
+///
+/// A request with an `Idempotency-Key` header that was seen before
+/// gets the same answer again, without adding the deal twice.
+async fn post_deal(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<DealRequest>,
+) -> Result<Response, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let key = idempotency_key(&headers)?;
+            let bid: Bid = request
+                .bid
+                .parse()
+                .map_err(|e: BidErr| ApiError::bad_request(&e.to_string()))?;
+
+            let _submission = one_at_a_time(&game_id, key.as_deref()).await;
+            let mut game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if let Some(response) = replay(db.clone(), &game_id, key.as_deref()).await? {
+                return Ok(response);
+            }
+
+            if game.changed_since(request.version) {
+                return Err(Error::Conflict.into());
+            }
+
+            let round = request.round.unwrap_or(game.deals.len());
+            let deal = build_deal(
+                &game,
+                round,
+                &request.team,
+                &request.opponents,
+                &request.made,
+                Some(bid),
+                request.tricks,
+            )?;
+
+            let (from, update): (_, fn(Game) -> Update) = if round < game.deals.len() {
+                game.replace_deal(round, deal)?;
+                (round, Update::Game)
+            } else {
+                game.add_deal(deal)?;
+                (game.deals.len() - 1, Update::Points)
+            };
+            commit_rounds(db.clone(), token.user, &game_id, &mut game, from, update).await?;
+
+            let response = Json(GameBody {
+                id: game_id.clone(),
+                game,
+            })
+            .into_response();
+            Ok(remember(db, &game_id, key, response).await?)
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+#[derive(Deserialize)]
+struct UndoRequest {
+    /// the version of the game the undo was based on
+    version: Option<i64>,
+}
+
+/// Undoes the last round and answers with the whole game
+async fn undo(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    request: Option<Json<UndoRequest>>,
+) -> Result<Response, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let key = idempotency_key(&headers)?;
+
+            let _submission = one_at_a_time(&game_id, key.as_deref()).await;
+            let mut game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            if let Some(response) = replay(db.clone(), &game_id, key.as_deref()).await? {
+                return Ok(response);
+            }
+
+            // without a body, the undo is not based on any version
+            let version = request.and_then(|Json(request)| request.version);
+            if game.changed_since(version) {
+                return Err(Error::Conflict.into());
+            }
+
+            if game.finished {
+                return Err(Error::GameFinished.into());
+            }
+
+            if game.undo_deal().is_none() {
+                return Err(ApiError::bad_request("Geen rondes om ongedaan te maken"));
+            }
+            // only the undone round has to go
+            let from = game.deals.len();
+            commit_rounds(
+                db.clone(),
+                token.user,
+                &game_id,
+                &mut game,
+                from,
+                Update::Game,
+            )
+            .await?;
+
+            let response = Json(GameBody {
+                id: game_id.clone(),
+                game,
+            })
+            .into_response();
+            Ok(remember(db, &game_id, key, response).await?)
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// The key a client gave to make a request safe to send again
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    headers
+        .get("Idempotency-Key")
+        .map(|key| {
+            key.to_str()
+                .map(str::to_string)
+                .map_err(|_| ApiError::bad_request("ongeldige Idempotency-Key"))
+        })
+        .transpose()
+}
+
+async fn players(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+) -> Result<Json<Vec<PlayerLinkStatus>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let game = db::get_game(db.clone(), token.user, game_id.clone()).await?;
+
+            Ok(Json(player_links(db, &game_id, &game).await?))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// Invites the account with a login id for one of the players of a game
+#[derive(Deserialize)]
+struct InviteRequest {
+    user_id: String,
+    player: String,
+}
+
+async fn invite_player(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Json(request): Json<InviteRequest>,
+) -> Result<Json<Vec<PlayerLinkStatus>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+
+            db::invite_player(
+                db.clone(),
+                token.user,
+                game_id.clone(),
+                request.user_id,
+                request.player,
+            )
+            .await?;
+
+            let player_links = player_links(db, &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game, player_links.clone()));
+
+            Ok(Json(player_links))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// Gives the account linked to a player another role
+#[derive(Deserialize)]
+struct RoleRequest {
+    player: String,
+    role: Role,
+}
+
+async fn set_role(
+    State(db): State<Db>,
+    Path(game_id): Path<String>,
+    jar: CookieJar,
+    Json(request): Json<RoleRequest>,
+) -> Result<Json<Vec<PlayerLinkStatus>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            db::set_role(
+                db.clone(),
+                token.user.clone(),
+                game_id.clone(),
+                request.player,
+                request.role,
+            )
+            .await?;
+
+            let game = db::get_game(db.clone(), token.user, game_id.clone()).await?;
+            let player_links = player_links(db, &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game, player_links.clone()));
+
+            Ok(Json(player_links))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// Takes a seat in a game with its join code
+#[derive(Deserialize)]
+struct JoinRequest {
+    code: String,
+    player: String,
+}
+
+async fn join_game(
+    State(db): State<Db>,
+    jar: CookieJar,
+    Json(request): Json<JoinRequest>,
+) -> Result<Json<GameBody>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let code = request.code.trim().to_uppercase();
+            let game_id =
+                db::join_with_code(db.clone(), token.user.clone(), &code, request.player).await?;
+
+            let game = db::get_game(db.clone(), token.user, game_id.clone()).await?;
+            let player_links = player_links(db.clone(), &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game.clone(), player_links));
+
+            rating::update(Change::Game(game_id.clone()));
+
+            Ok(Json(GameBody { id: game_id, game }))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+async fn invites(State(db): State<Db>, jar: CookieJar) -> Result<Json<Vec<Invite>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        { Ok(Json(db::get_invites(db, token.user).await?)) },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// Takes the seat of an invite and answers with the invites that are left
+async fn accept_invite(
+    State(db): State<Db>,
+    Path(invite_id): Path<i64>,
+    jar: CookieJar,
+) -> Result<Json<Vec<Invite>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            let game_id = db::accept_invite(db.clone(), token.user.clone(), invite_id).await?;
+
+            let game = db::get_game(db.clone(), token.user.clone(), game_id.clone()).await?;
+            let player_links = player_links(db.clone(), &game_id, &game).await?;
+            live::publish(&game_id, Update::Settings(game, player_links));
+
+            rating::update(Change::Game(game_id.clone()));
+
+            Ok(Json(db::get_invites(db, token.user).await?))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+/// Throws an invite away and answers with the invites that are left
+async fn decline_invite(
+    State(db): State<Db>,
+    Path(invite_id): Path<i64>,
+    jar: CookieJar,
+) -> Result<Json<Vec<Invite>>, ApiError> {
+    auth!(
+        jar,
+        token,
+        {
+            db::decline_invite(db.clone(), token.user.clone(), invite_id).await?;
+
+            Ok(Json(db::get_invites(db, token.user).await?))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+async fn ratings(
+    State(db): State<Db>,
+    jar: CookieJar,
+) -> Result<Json<Vec<LeaderboardEntry>>, ApiError> {
+    auth!(
+        jar,
+        _token,
+        {
+            let ratings = db::get_ratings(db)
+                .await?
+                .into_iter()
+                .map(LeaderboardEntry::new)
+                .collect();
+
+            Ok(Json(ratings))
+        },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+async fn rating_history(
+    State(db): State<Db>,
+    Path(email): Path<String>,
+    jar: CookieJar,
+) -> Result<Json<Vec<RatingChange>>, ApiError> {
+    auth!(
+        jar,
+        _token,
+        { Ok(Json(db::get_rating_history(db, email).await?)) },
+        { Err(ApiError::unauthorized()) }
+    )
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([("Content-Type", "application/json")], OPENAPI)
+}
@@ -0,0 +1,205 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BidErr, Error};
+use crate::whist::Bid;
+
+/// A scoring table: decides how many points a bid is worth
+pub trait ScoringRules {
+    /// the amount of points that the playing team gets
+    ///
+    /// if it is a solo game, the point amount is to be multiplied by 3.
+    /// A bid that is not in the table, like `Bid::Solo(9)`, is an error.
+    fn points(&self, bid: &Bid, achieved: i16) -> Result<i16, Error>;
+}
+
+/// The rule set a game is scored with.
+///
+/// This is stored together with the game, so that changing the default
+/// rule set never changes the scores of games that were already played.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleSet {
+    #[default]
+    Whisthub,
+}
+
+impl RuleSet {
+    pub const ALL: [RuleSet; 1] = [RuleSet::Whisthub];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleSet::Whisthub => "whisthub",
+        }
+    }
+
+    fn rules(&self) -> &dyn ScoringRules {
+        match self {
+            RuleSet::Whisthub => &Whisthub,
+        }
+    }
+}
+
+impl FromStr for RuleSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RuleSet::ALL
+            .into_iter()
+            .find(|rules| rules.as_str() == s)
+            .ok_or(Error::UnknownRules)
+    }
+}
+
+impl Display for RuleSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleSet::Whisthub => write!(f, "Whisthub"),
+        }
+    }
+}
+
+impl ScoringRules for RuleSet {
+    fn points(&self, bid: &Bid, achieved: i16) -> Result<i16, Error> {
+        self.rules().points(bid, achieved)
+    }
+}
+
+/// Scoring based on the [Whisthub rules](https://www.whisthub.com/rules)
+pub struct Whisthub;
+
+impl ScoringRules for Whisthub {
+    fn points(&self, bid: &Bid, achieved: i16) -> Result<i16, Error> {
+        let points = match bid {
+            Bid::Solo(5) => {
+                if 5 <= achieved {
+                    (achieved - 2).min(6)
+                } else {
+                    achieved - 8
+                }
+            }
+            Bid::Solo(6) => {
+                if 6 <= achieved {
+                    (achieved - 2).min(6)
+                } else {
+                    achieved - 10
+                }
+            }
+            Bid::Solo(7) => {
+                if 7 <= achieved {
+                    (achieved - 2).min(6)
+                } else {
+                    achieved - 12
+                }
+            }
+            Bid::Solo(8) => {
+                if 8 <= achieved {
+                    7
+                } else {
+                    achieved - 15
+                }
+            }
+            Bid::Samen(8) => {
+                if achieved == 13 {
+                    30
+                } else if 8 <= achieved {
+                    8 + 3 * (achieved - 8)
+                } else {
+                    3 * (achieved - 8) - 8
+                }
+            }
+            Bid::Samen(9) => {
+                if achieved == 13 {
+                    30
+                } else if 9 <= achieved {
+                    11 + 3 * (achieved - 9)
+                } else {
+                    3 * (achieved - 9) - 11
+                }
+            }
+            Bid::Samen(10) => {
+                if achieved == 13 {
+                    30
+                } else if 10 <= achieved {
+                    14 + 3 * (achieved - 10)
+                } else {
+                    3 * (achieved - 10) - 14
+                }
+            }
+            Bid::Samen(11) => {
+                if achieved == 13 {
+                    30
+                } else if 11 <= achieved {
+                    17 + 3 * (achieved - 11)
+                } else {
+                    3 * (achieved - 11) - 17
+                }
+            }
+            Bid::Samen(12) => {
+                if achieved == 13 {
+                    30
+                } else if 12 == achieved {
+                    20
+                } else {
+                    3 * (achieved - 12) - 20
+                }
+            }
+            Bid::Samen(13) => {
+                if achieved == 13 {
+                    30
+                } else {
+                    3 * (achieved - 13) - 23
+                }
+            }
+            Bid::Abondance(9) => match achieved {
+                9 => 10,
+                10 => 15,
+                11 => 20,
+                12 => 30,
+                13 => 60,
+                _ => -10,
+            },
+            Bid::Abondance(10) => match achieved {
+                10 => 15,
+                11 => 20,
+                12 => 30,
+                13 => 60,
+                _ => -15,
+            },
+            Bid::Abondance(11) => match achieved {
+                11 => 20,
+                12 => 30,
+                13 => 60,
+                _ => -20,
+            },
+            Bid::Abondance(12) => match achieved {
+                12 => 30,
+                13 => 60,
+                _ => -30,
+            },
+            Bid::SmallMisery => match achieved {
+                0 => 6,
+                _ => -6,
+            },
+            Bid::Trull => match achieved {
+                x if x >= 8 => 16,
+                _ => -16,
+            },
+            Bid::LargeMisery => match achieved {
+                0 => 12,
+                _ => -12,
+            },
+            Bid::OpenMisery => match achieved {
+                0 => 24,
+                _ => -24,
+            },
+            Bid::GrandSlam => match achieved {
+                13 => 60,
+                _ => -60,
+            },
+            _ => return Err(BidErr(bid.to_string()).into()),
+        };
+        Ok(points)
+    }
+}
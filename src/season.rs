@@ -0,0 +1,220 @@
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAY: i64 = 24 * 60 * 60;
+
+static SEASONS: OnceLock<Vec<Season>> = OnceLock::new();
+
+/// A season of the leaderboard, in unix times from midnight (UTC)
+/// on its start date up to midnight on its end date
+pub struct Season {
+    pub name: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Season {
+    /// Parses a season from the config, as `name:YYYY-MM-DD:YYYY-MM-DD`
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split(':');
+        let name = parts.next().filter(|name| !name.is_empty())?;
+        let start = parse_date(parts.next()?)?;
+        let end = parse_date(parts.next()?)?;
+        if parts.next().is_some() || end <= start {
+            return None;
+        }
+
+        Some(Season {
+            name: name.to_string(),
+            start,
+            end,
+        })
+    }
+
+    pub fn has_ended(&self) -> bool {
+        self.end <= now()
+    }
+}
+
+/// The seasons set with `SEASONS` in the config, separated by commas:
+/// `SEASONS=najaar-2026:2026-09-01:2027-01-01,voorjaar-2027:2027-01-01:2027-07-01`
+pub fn seasons() -> &'static [Season] {
+    SEASONS.get_or_init(|| {
+        let Ok(config) = crate::config("SEASONS") else {
+            return vec![];
+        };
+
+        config
+            .split(',')
+            .filter(|value| !value.trim().is_empty())
+            .filter_map(|value| {
+                let season = Season::parse(value);
+                if season.is_none() {
+                    log::warn!("skipping the season {value:?}, it is not name:start:end");
+                }
+                season
+            })
+            .collect()
+    })
+}
+
+/// The games a leaderboard ranks the players by
+pub enum Period {
+    /// All games, with the ratings of the players
+    AllTime,
+    /// The games of the last months
+    Months(u32),
+    Season(&'static Season),
+}
+
+impl Period {
+    /// Parses the period as it is chosen on the leaderboard, all time by default
+    pub fn parse(value: &str) -> Self {
+        if let Some(months) = value.strip_prefix("maanden-") {
+            if let Some(months) = months.parse().ok().filter(|m| (1..=120).contains(m)) {
+                return Period::Months(months);
+            }
+        }
+        if let Some(name) = value.strip_prefix("seizoen-") {
+            if let Some(season) = seasons().iter().find(|season| season.name == name) {
+                return Period::Season(season);
+            }
+        }
+        Period::AllTime
+    }
+
+    /// The periods to choose from on the leaderboard
+    pub fn choices() -> Vec<Period> {
+        [
+            Period::AllTime,
+            Period::Months(3),
+            Period::Months(6),
+            Period::Months(12),
+        ]
+        .into_iter()
+        .chain(seasons().iter().map(Period::Season))
+        .collect()
+    }
+
+    /// The value of the period on the leaderboard
+    pub fn value(&self) -> String {
+        match self {
+            Period::AllTime => "alles".into(),
+            Period::Months(months) => format!("maanden-{months}"),
+            Period::Season(season) => format!("seizoen-{}", season.name),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Period::AllTime => "altijd".into(),
+            Period::Months(months) => format!("laatste {months} maanden"),
+            Period::Season(season) if season.has_ended() => format!("seizoen {}", season.name),
+            Period::Season(season) => format!("seizoen {} (loopt)", season.name),
+        }
+    }
+
+    /// The games that were started in this range of unix times count,
+    /// `None` when all games count
+    pub fn range(&self) -> Option<(i64, i64)> {
+        match self {
+            Period::AllTime => None,
+            Period::Months(months) => Some((months_ago(now(), *months), i64::MAX)),
+            Period::Season(season) => Some((season.start, season.end)),
+        }
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Parses a `YYYY-MM-DD` date to the unix time of its midnight (UTC)
+fn parse_date(value: &str) -> Option<i64> {
+    let mut parts = value.trim().split('-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * DAY)
+}
+
+/// The unix time `months` months before `time`, on the same day of the month
+fn months_ago(time: i64, months: u32) -> i64 {
+    let (year, month, day) = civil_from_days(time.div_euclid(DAY));
+    let months = year * 12 + (month - 1) - months as i64;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+
+    days_from_civil(year, month, day.min(28)) * DAY + time.rem_euclid(DAY)
+}
+
+/// The days since 1970-01-01 of a date in the proleptic Gregorian calendar,
+/// following <http://howardhinnant.github.io/date_algorithms.html>
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a number of days since 1970-01-01, the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_follow_the_calendar() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19782);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        for days in [-1, 0, 11016, 11017, 19782, 20000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(
+            parse_date("2026-09-01"),
+            Some(days_from_civil(2026, 9, 1) * DAY)
+        );
+        assert_eq!(parse_date("2026-13-01"), None);
+    }
+
+    #[test]
+    fn months_ago_keeps_the_day() {
+        let time = days_from_civil(2026, 3, 15) * DAY + 3600;
+        assert_eq!(
+            months_ago(time, 3),
+            days_from_civil(2025, 12, 15) * DAY + 3600
+        );
+        assert_eq!(
+            months_ago(time, 14),
+            days_from_civil(2025, 1, 15) * DAY + 3600
+        );
+
+        // a day that the earlier month does not have
+        let time = days_from_civil(2026, 5, 31) * DAY;
+        assert_eq!(months_ago(time, 3), days_from_civil(2026, 2, 28) * DAY);
+    }
+}
@@ -1,10 +1,14 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
 use askama::Template;
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Response};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::error::Error;
-use crate::whist::{Game, Players, Points};
+use crate::scoring::RuleSet;
+use crate::whist::{Game, Points};
 
 #[derive(Template)]
 #[template(path = "index.html")]
@@ -14,54 +18,123 @@ pub struct IndexTemplate {}
 #[template(path = "login.html")]
 pub struct LoginTemplate {}
 
+#[derive(Serialize)]
 pub struct LeaderboardEntry {
     pub email: String,
     pub rating: i32,
+    /// whether the player has too few rated games for a reliable rating
+    pub provisional: bool,
+}
+
+impl LeaderboardEntry {
+    pub fn new((email, rating, games): (String, i32, i64)) -> Self {
+        LeaderboardEntry {
+            email,
+            rating,
+            provisional: games < crate::rating::PROVISIONAL_GAMES,
+        }
+    }
 }
 
 #[derive(Template)]
 #[template(path = "main.html")]
 pub struct MainTemplate {
+    pub email: String,
     pub rating: i32,
+    /// the number of open invites
+    pub invites: i64,
 }
 
 #[derive(Template)]
 #[template(path = "leaderboard.html")]
 pub struct LeaderboardTemplate {
     pub leaderboard: Vec<LeaderboardEntry>,
+    /// the periods to choose from, as (value, label)
+    pub periods: Vec<(String, String)>,
+    /// the chosen period
+    pub period: String,
+    /// the least rated games of a player on the leaderboard
+    pub minimum: i64,
 }
 
 #[derive(Template)]
 #[template(path = "leaderboard_full.html")]
 pub struct FullLeaderboardTemplate {
     pub leaderboard: Vec<LeaderboardEntry>,
+    pub periods: Vec<(String, String)>,
+    pub period: String,
+    pub minimum: i64,
+}
+
+/// What one game did to the rating of a player
+#[derive(Serialize)]
+pub struct RatingChange {
+    pub game_id: i64,
+    /// the name of the game
+    pub game: String,
+    /// the rating after the game
+    pub rating: i32,
+    pub delta: i32,
+}
+
+#[derive(Template)]
+#[template(path = "rating_history.html")]
+pub struct RatingHistoryTemplate {
+    pub email: String,
+    pub history: Vec<RatingChange>,
+}
+
+#[derive(Template)]
+#[template(path = "rating_history_full.html")]
+pub struct FullRatingHistoryTemplate {
+    pub email: String,
+    pub history: Vec<RatingChange>,
 }
 
 #[derive(Template)]
 #[template(path = "deal_form.html")]
 pub struct DealFormTemplate {
     pub id: String,
+    /// the round the deal form is for, `game.deals.len()` for a new round
+    pub round: usize,
     pub game: Game,
     pub solobids: Vec<String>,
     pub duobids: Vec<String>,
+    pub miserybids: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "correction_form.html")]
+pub struct CorrectionFormTemplate {
+    pub id: String,
+    pub game: Game,
+    /// The most points a player can get or lose with the correction
+    pub max: i16,
 }
 
 #[derive(Template)]
 #[template(path = "game_full.html")]
 pub struct FullGameTemplate {
     pub id: String,
+    pub round: usize,
     pub game: Game,
+    pub role: Role,
     pub solobids: Vec<String>,
     pub duobids: Vec<String>,
+    pub miserybids: Vec<String>,
 }
 
 #[derive(Template)]
 #[template(path = "game.html")]
 pub struct GameTemplate {
     pub id: String,
+    pub round: usize,
     pub game: Game,
+    /// Only editors and owners get the controls to change the rounds
+    pub role: Role,
     pub solobids: Vec<String>,
     pub duobids: Vec<String>,
+    pub miserybids: Vec<String>,
 }
 
 #[derive(Template, Clone)]
@@ -100,13 +173,33 @@ pub struct SuccessTemplate {
     pub message: String,
 }
 
+/// A fresh random key for a rendered form, so that the server can
+/// recognise the form when it is submitted more than once
+pub fn idempotency_key() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// The game as it is now, with an alert that a change could not be saved
+#[derive(Template)]
+#[template(path = "conflict.html")]
+pub struct ConflictTemplate {
+    pub alert: String,
+    pub id: String,
+    pub round: usize,
+    pub game: Game,
+    pub role: Role,
+    pub solobids: Vec<String>,
+    pub duobids: Vec<String>,
+    pub miserybids: Vec<String>,
+}
+
 #[derive(Template)]
 #[template(path = "points.html")]
 pub struct PointsTemplate {
     pub id: String,
     pub points: Points,
-    pub players: Players,
-    pub scores: Points,
+    pub game: Game,
+    pub role: Role,
 }
 
 #[derive(Template)]
@@ -159,31 +252,250 @@ pub struct Chart {
     pub scores: Vec<ChartScore>,
 }
 
+/// An API token of a user, without the token itself
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    pub last_used: Option<i64>,
+}
+
+impl ApiToken {
+    /// When the token was used last, in days
+    pub fn last_used_text(&self) -> String {
+        let Some(last_used) = self.last_used else {
+            return "nooit gebruikt".into();
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(last_used);
+
+        match (now - last_used) / 86400 {
+            0 => "vandaag gebruikt".into(),
+            1 => "gisteren gebruikt".into(),
+            days => format!("{days} dagen geleden gebruikt"),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "api_tokens.html")]
+pub struct ApiTokensTemplate {
+    pub tokens: Vec<ApiToken>,
+    /// a token that was just created, which is only shown this once
+    pub new_token: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "api_tokens_full.html")]
+pub struct FullApiTokensTemplate {
+    pub tokens: Vec<ApiToken>,
+    pub new_token: Option<String>,
+}
+
+/// A game in the trash of a user
+pub struct TrashedGame {
+    pub id: String,
+    pub name: String,
+    /// how long the game can still be restored
+    pub seconds_left: i64,
+}
+
+impl TrashedGame {
+    pub fn days_left_text(&self) -> String {
+        match (self.seconds_left + 86399) / 86400 {
+            1 => "nog 1 dag".into(),
+            days => format!("nog {days} dagen"),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "trash.html")]
+pub struct TrashTemplate {
+    pub games: Vec<TrashedGame>,
+}
+
+#[derive(Template)]
+#[template(path = "trash_full.html")]
+pub struct FullTrashTemplate {
+    pub games: Vec<TrashedGame>,
+}
+
+/// The share link of a game, in its settings
+#[derive(Template)]
+#[template(path = "share.html")]
+pub struct ShareTemplate {
+    pub id: String,
+    pub link: Option<String>,
+}
+
+/// A game as anybody with its share link sees it: without forms and without emails
+#[derive(Template)]
+#[template(path = "shared_game.html")]
+pub struct SharedGameTemplate {
+    pub game_id: String,
+    pub signature: String,
+    pub game: Game,
+    /// the rendered `Chart` of the game
+    pub chart: String,
+}
+
+#[derive(Template)]
+#[template(path = "shared_game_full.html")]
+pub struct FullSharedGameTemplate {
+    pub game_id: String,
+    pub signature: String,
+    pub game: Game,
+    pub chart: String,
+}
+
+/// The join code of a game, as shown in its settings
+pub struct ActiveJoinCode {
+    pub code: String,
+    /// the QR of the link to join with this code
+    pub svg: String,
+    pub minutes_left: i64,
+}
+
+#[derive(Template)]
+#[template(path = "join_code.html")]
+pub struct JoinCodeTemplate {
+    pub id: String,
+    pub join_code: Option<ActiveJoinCode>,
+}
+
+/// Joining a game with a code: first the code, then the seat to take
+#[derive(Template)]
+#[template(path = "join.html")]
+pub struct JoinTemplate {
+    pub code: String,
+    pub game_name: Option<String>,
+    /// the players of the game that no account is linked to yet
+    pub seats: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "join_full.html")]
+pub struct FullJoinTemplate {
+    pub code: String,
+    pub game_name: Option<String>,
+    pub seats: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// An invite to take a seat in a game
+#[derive(Serialize)]
+pub struct Invite {
+    pub id: i64,
+    pub game_id: String,
+    pub game_name: String,
+    pub alias: String,
+    pub invited_by: String,
+}
+
+#[derive(Template)]
+#[template(path = "invites.html")]
+pub struct InvitesTemplate {
+    pub invites: Vec<Invite>,
+}
+
+#[derive(Template)]
+#[template(path = "invites_full.html")]
+pub struct FullInvitesTemplate {
+    pub invites: Vec<Invite>,
+}
+
+/// What a player may do in a game, every role may do all that the roles before it may
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// only follows the game
+    Viewer,
+    /// adds and changes rounds
+    Editor,
+    /// links players, decides their roles and deletes the game
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn is_owner(&self) -> bool {
+        *self == Role::Owner
+    }
+
+    /// Whether this role may add and change rounds
+    pub fn can_edit(&self) -> bool {
+        *self >= Role::Editor
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or(Error::UnknownRole)
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "kijker"),
+            Role::Editor => write!(f, "speler"),
+            Role::Owner => write!(f, "eigenaar"),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LinkedPlayer {
     pub alias: String,
     pub email: String,
+    pub role: Role,
 }
 
+#[derive(Clone, Serialize)]
 pub struct PlayerLinkStatus {
     pub name: String,
     pub linked: bool,
+    /// the role of the account linked to this seat
+    pub role: Option<Role>,
+    /// somebody was invited for this seat, but did not answer yet
+    pub invited: bool,
+    pub dealer: bool,
 }
 
 #[derive(Template)]
 #[template(path = "game_settings.html")]
 pub struct GameSettingsTemplate {
     pub id: String,
-    pub game_name: String,
+    pub game: Game,
     pub player_links: Vec<PlayerLinkStatus>,
+    /// the role of whoever looks at the settings
+    pub role: Role,
 }
 
 #[derive(Template)]
 #[template(path = "game_settings_full.html")]
 pub struct FullGameSettingsTemplate {
     pub id: String,
-    pub game_name: String,
+    pub game: Game,
     pub player_links: Vec<PlayerLinkStatus>,
+    pub role: Role,
 }
 
 // Turns askama templates into responses that can be handled by server
@@ -229,6 +541,9 @@ impl From<StatusCode> for AlertTemplate {
 
 impl From<Error> for AlertTemplate {
     fn from(value: Error) -> Self {
+        if let Error::NotAllowed(_) = value {
+            return value.into_alert();
+        }
         AlertTemplate {
             code: StatusCode::INTERNAL_SERVER_ERROR,
             alert: value.to_string(),
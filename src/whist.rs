@@ -1,7 +1,12 @@
-use std::ops::{Add, AddAssign, Index, IndexMut, Sub};
+use std::fmt::Display;
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Sub};
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::error::{BidErr, Error};
+use crate::scoring::{RuleSet, ScoringRules};
+
 /// All bids that are played with one player
 const SOLOBIDS: [&str; 12] = [
     "Solo 5",
@@ -18,6 +23,12 @@ const SOLOBIDS: [&str; 12] = [
     "Solo Slim",
 ];
 
+/// All bids that several players can play at once, each on their own
+const MISERYBIDS: [&str; 3] = ["Kleine Miserie", "Grote Miserie", "Open Miserie"];
+
+/// The most points a correction can give or take from a single player
+pub const MAX_CORRECTION: i16 = 1000;
+
 /// All bids that are played with two players
 const DUOBIDS: [&str; 7] = [
     "Samen 8", "Samen 9", "Samen 10", "Samen 11", "Samen 12", "Samen 13", "Troel",
@@ -31,6 +42,10 @@ pub fn duo_bids() -> Vec<String> {
     DUOBIDS.iter().map(|s| s.to_string()).collect()
 }
 
+pub fn misery_bids() -> Vec<String> {
+    MISERYBIDS.iter().map(|s| s.to_string()).collect()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Bid {
     Solo(i16),
@@ -43,9 +58,11 @@ pub enum Bid {
     GrandSlam,
 }
 
-impl From<&str> for Bid {
-    fn from(value: &str) -> Self {
-        match value.to_lowercase().as_str() {
+impl FromStr for Bid {
+    type Err = BidErr;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let bid = match value.trim().to_lowercase().as_str() {
             "solo 5" => Bid::Solo(5),
             "solo 6" => Bid::Solo(6),
             "solo 7" => Bid::Solo(7),
@@ -60,150 +77,55 @@ impl From<&str> for Bid {
             "abondance 10" => Bid::Abondance(10),
             "abondance 11" => Bid::Abondance(11),
             "abondance 12" => Bid::Abondance(12),
-            "abondance 13" => Bid::Abondance(13),
             "solo slim" => Bid::GrandSlam,
             "kleine miserie" => Bid::SmallMisery,
             "grote miserie" => Bid::LargeMisery,
             "open miserie" => Bid::OpenMisery,
             "troel" => Bid::Trull,
-            _ => unreachable!(),
-        }
+            _ => return Err(BidErr(value.to_string())),
+        };
+        Ok(bid)
+    }
+}
+
+impl TryFrom<&str> for Bid {
+    type Error = BidErr;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
     }
 }
 
 impl Bid {
-    /// the amount of points that the playing team gets
-    ///
-    /// if it is a solo game, the point amount is to be multiplied by 3
-    pub fn points(&self, achieved: i16) -> i16 {
+    /// Whether this bid is a misery, which several players can play at once
+    pub fn is_misery(&self) -> bool {
+        matches!(self, Bid::SmallMisery | Bid::LargeMisery | Bid::OpenMisery)
+    }
+
+    /// The tricks the playing team needs to make this bid,
+    /// for a misery the tricks it may get at most
+    pub fn tricks(&self) -> i16 {
         match self {
-            Bid::Solo(5) => {
-                if 5 <= achieved {
-                    (achieved - 2).min(6)
-                } else {
-                    achieved - 8
-                }
-            }
-            Bid::Solo(6) => {
-                if 6 <= achieved {
-                    (achieved - 2).min(6)
-                } else {
-                    achieved - 10
-                }
-            }
-            Bid::Solo(7) => {
-                if 7 <= achieved {
-                    (achieved - 2).min(6)
-                } else {
-                    achieved - 12
-                }
-            }
-            Bid::Solo(8) => {
-                if 8 <= achieved {
-                    7
-                } else {
-                    achieved - 15
-                }
-            }
-            Bid::Samen(8) => {
-                if achieved == 13 {
-                    30
-                } else if 8 <= achieved {
-                    8 + 3 * (achieved - 8)
-                } else {
-                    3 * (achieved - 8) - 8
-                }
-            }
-            Bid::Samen(9) => {
-                if achieved == 13 {
-                    30
-                } else if 9 <= achieved {
-                    11 + 3 * (achieved - 9)
-                } else {
-                    3 * (achieved - 9) - 11
-                }
-            }
-            Bid::Samen(10) => {
-                if achieved == 13 {
-                    30
-                } else if 10 <= achieved {
-                    14 + 3 * (achieved - 10)
-                } else {
-                    3 * (achieved - 10) - 14
-                }
-            }
-            Bid::Samen(11) => {
-                if achieved == 13 {
-                    30
-                } else if 11 <= achieved {
-                    17 + 3 * (achieved - 11)
-                } else {
-                    3 * (achieved - 11) - 17
-                }
-            }
-            Bid::Samen(12) => {
-                if achieved == 13 {
-                    30
-                } else if 12 == achieved {
-                    20
-                } else {
-                    3 * (achieved - 12) - 20
-                }
-            }
-            Bid::Samen(13) => {
-                if achieved == 13 {
-                    30
-                } else {
-                    3 * (achieved - 13) - 23
-                }
-            }
-            Bid::Abondance(9) => match achieved {
-                9 => 10,
-                10 => 15,
-                11 => 20,
-                12 => 30,
-                13 => 60,
-                _ => -10,
-            },
-            Bid::Abondance(10) => match achieved {
-                10 => 15,
-                11 => 20,
-                12 => 30,
-                13 => 60,
-                _ => -15,
-            },
-            Bid::Abondance(11) => match achieved {
-                11 => 20,
-                12 => 30,
-                13 => 60,
-                _ => -20,
-            },
-            Bid::Abondance(12) => match achieved {
-                12 => 30,
-                13 => 60,
-                _ => -30,
-            },
-            Bid::SmallMisery => match achieved {
-                0 => 6,
-                _ => -6,
-            },
-            Bid::Trull => match achieved {
-                x if x >= 8 => 16,
-                _ => -16,
-            },
-            Bid::LargeMisery => match achieved {
-                0 => 12,
-                _ => -12,
-            },
-            Bid::OpenMisery => match achieved {
-                0 => 24,
-                _ => -24,
-            },
-            Bid::GrandSlam => match achieved {
-                13 => 60,
-                _ => -60,
-            },
-            _ => unreachable!(),
+            Bid::Solo(n) | Bid::Samen(n) | Bid::Abondance(n) => *n,
+            Bid::Trull => 8,
+            Bid::GrandSlam => 13,
+            Bid::SmallMisery | Bid::LargeMisery | Bid::OpenMisery => 0,
+        }
+    }
+}
+
+/// Writes the bid the way it is shown in the deal form, so that it parses back
+impl Display for Bid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bid::Solo(n) => write!(f, "Solo {n}"),
+            Bid::Samen(n) => write!(f, "Samen {n}"),
+            Bid::Abondance(n) => write!(f, "Abondance {n}"),
+            Bid::SmallMisery => write!(f, "Kleine Miserie"),
+            Bid::Trull => write!(f, "Troel"),
+            Bid::LargeMisery => write!(f, "Grote Miserie"),
+            Bid::OpenMisery => write!(f, "Open Miserie"),
+            Bid::GrandSlam => write!(f, "Solo Slim"),
         }
     }
 }
@@ -219,6 +141,21 @@ impl Points {
     pub fn positive(&self, i: &usize) -> bool {
         self[*i] > 0
     }
+
+    /// Whether these points add up to nothing
+    pub fn is_zero_sum(&self) -> bool {
+        self.0.iter().map(|x| i32::from(*x)).sum::<i32>() == 0
+    }
+
+    /// Adds other points to these, `None` when a score gets too large
+    pub fn checked_add(&self, rhs: &Points) -> Option<Points> {
+        self.0
+            .iter()
+            .zip(&rhs.0)
+            .map(|(x, y)| x.checked_add(*y))
+            .collect::<Option<Vec<_>>>()
+            .map(Points)
+    }
 }
 
 impl Default for Points {
@@ -269,6 +206,14 @@ impl Add for Points {
     }
 }
 
+impl Mul<i16> for Points {
+    type Output = Points;
+
+    fn mul(self, rhs: i16) -> Self::Output {
+        Points(self.0.into_iter().map(|x| x * rhs).collect())
+    }
+}
+
 impl<'b> Sub<&'b Points> for &Points {
     type Output = Points;
 
@@ -309,6 +254,13 @@ impl Players {
         self.0.len() == 0
     }
 
+    /// Finds the index of a player by name, ignoring case
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|p| p.to_lowercase() == name.to_lowercase())
+    }
+
     pub fn opt_add_player(&mut self, opt_player: &str) {
         if !opt_player.is_empty() {
             self.0.push(opt_player.to_string())
@@ -366,9 +318,56 @@ pub struct Game {
     /// The Name of the Game
     pub name: String,
     pub players: Players,
-    /// cumulative points of the game
+    /// cumulative points of the game, derived from the rounds
     pub scores: Vec<Points>,
-    pub deals: Vec<Deal>,
+    /// every round of the game, one for each score after the first
+    pub deals: Vec<Round>,
+    /// The scoring rules this game was created with
+    #[serde(default)]
+    pub rules: RuleSet,
+    /// Index of the player that dealt the first round.
+    ///
+    /// The players are stored in seating order, so the dealer of every
+    /// later round follows from this, the number of rounds played and
+    /// the dealers chosen since.
+    #[serde(default)]
+    pub first_dealer: usize,
+    /// Dealers that were chosen during the game, as the round from which
+    /// on they count and the player that deals that round, by round.
+    /// The rounds before a change keep the dealers they were played with.
+    #[serde(default)]
+    pub dealer_changes: Vec<(usize, usize)>,
+    /// Rounds that were undone, the last one on top.
+    /// They can be redone until a new round is added.
+    #[serde(default)]
+    pub undone: Vec<Round>,
+    /// When the game ends by itself, if ever
+    #[serde(default)]
+    pub end: Option<EndCondition>,
+    /// A finished game is locked: its rounds can no longer change
+    #[serde(default)]
+    pub finished: bool,
+    /// Goes up with every save, to notice changes made at the same time
+    #[serde(default)]
+    pub version: i64,
+}
+
+/// Decides when a game is over
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EndCondition {
+    /// after this many played deals
+    Rounds(usize),
+    /// as soon as a player reaches this score
+    Score(i16),
+}
+
+impl Display for EndCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndCondition::Rounds(rounds) => write!(f, "na {rounds} rondes"),
+            EndCondition::Score(score) => write!(f, "tot {score} punten"),
+        }
+    }
 }
 
 /// Team holds indexes into the Player struct that define the team
@@ -376,10 +375,30 @@ pub struct Game {
 pub enum Team {
     Solo(usize, (usize, usize, usize)),
     Duo((usize, usize), (usize, usize)),
+    /// Several players that each play a misery on their own,
+    /// together with the tricks they got, and the other players
+    Misery(Vec<(usize, i16)>, Vec<usize>),
+}
+
+impl Team {
+    /// All player indexes that took part, team first
+    pub fn seats(&self) -> Vec<usize> {
+        match self {
+            Team::Solo(player, (o1, o2, o3)) => vec![*player, *o1, *o2, *o3],
+            Team::Duo((p1, p2), (o1, o2)) => vec![*p1, *p2, *o1, *o2],
+            Team::Misery(players, opps) => players
+                .iter()
+                .map(|(player, _)| *player)
+                .chain(opps.iter().copied())
+                .collect(),
+        }
+    }
 }
 
 /// Keeps the results of a game
 /// team is in relation to the Players struct that is defined elsewhere
+///
+/// for a `Team::Misery`, the tricks are kept per player in the team
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Deal {
     pub team: Team,
@@ -387,14 +406,86 @@ pub struct Deal {
     pub achieved: i16,
 }
 
+/// One round in the history of a game
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "RoundRepr")]
+pub enum Round {
+    /// A deal that was played and scored
+    Deal(Deal),
+    /// Everybody passed, the cards are dealt again
+    /// and the next played deal counts double
+    AllPass,
+    /// A manual, zero-sum change of the scores, like the penalty for a
+    /// renonce. Nobody deals for it, so the dealer does not move on.
+    Correction { reason: String, points: Points },
+}
+
+/// Games saved before rounds existed only hold plain deals
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RoundRepr {
+    Tagged(TaggedRound),
+    Legacy(Deal),
+}
+
+#[derive(Deserialize)]
+enum TaggedRound {
+    Deal(Deal),
+    AllPass,
+    Correction { reason: String, points: Points },
+}
+
+impl From<RoundRepr> for Round {
+    fn from(value: RoundRepr) -> Self {
+        match value {
+            RoundRepr::Tagged(TaggedRound::Deal(deal)) | RoundRepr::Legacy(deal) => {
+                Round::Deal(deal)
+            }
+            RoundRepr::Tagged(TaggedRound::AllPass) => Round::AllPass,
+            RoundRepr::Tagged(TaggedRound::Correction { reason, points }) => {
+                Round::Correction { reason, points }
+            }
+        }
+    }
+}
+
+/// A side that played in a deal: the playing team,
+/// its opponents and whether the team made its bid
+pub type Side = (Vec<usize>, Vec<usize>, bool);
+
 impl Deal {
-    pub fn to_points(self, num_players: usize) -> Points {
-        let mut points = Points::new(num_players);
+    /// The sides that played against each other in this deal.
+    /// Every misery is a side of its own, against all other players.
+    pub fn sides(&self, rules: &impl ScoringRules) -> Result<Vec<Side>, Error> {
+        let made = |achieved| rules.points(&self.bid, achieved).map(|points| points > 0);
+        let sides = match &self.team {
+            Team::Solo(player, (o1, o2, o3)) => {
+                vec![(vec![*player], vec![*o1, *o2, *o3], made(self.achieved)?)]
+            }
+            Team::Duo((p1, p2), (o1, o2)) => {
+                vec![(vec![*p1, *p2], vec![*o1, *o2], made(self.achieved)?)]
+            }
+            Team::Misery(players, _) => {
+                let seats = self.team.seats();
+                players
+                    .iter()
+                    .map(|&(player, achieved)| {
+                        let others = seats.iter().copied().filter(|&s| s != player).collect();
+                        Ok((vec![player], others, made(achieved)?))
+                    })
+                    .collect::<Result<_, Error>>()?
+            }
+        };
+        Ok(sides)
+    }
 
-        let team_point = self.bid.points(self.achieved);
+    pub fn to_points(self, num_players: usize, rules: &impl ScoringRules) -> Result<Points, Error> {
+        let mut points = Points::new(num_players);
 
         match self.team {
             Team::Solo(player, opps) => {
+                let team_point = rules.points(&self.bid, self.achieved)?;
+
                 points[player] = 3 * team_point;
 
                 points[opps.0] = -team_point;
@@ -402,51 +493,457 @@ impl Deal {
                 points[opps.2] = -team_point;
             }
             Team::Duo(players, opps) => {
+                let team_point = rules.points(&self.bid, self.achieved)?;
                 points[players.0] = team_point;
                 points[players.1] = team_point;
 
                 points[opps.0] = -team_point;
                 points[opps.1] = -team_point;
             }
+            Team::Misery(players, opps) => {
+                // every misery is settled on its own against the three others
+                let seats: Vec<usize> = players.iter().map(|(p, _)| *p).chain(opps).collect();
+                for (player, achieved) in players {
+                    let team_point = rules.points(&self.bid, achieved)?;
+                    for &seat in &seats {
+                        if seat == player {
+                            points[seat] += 3 * team_point;
+                        } else {
+                            points[seat] -= team_point;
+                        }
+                    }
+                }
+            }
         }
 
-        points
+        Ok(points)
     }
 }
 
 impl Game {
-    pub fn new<P: Into<Players>>(name: String, players: P) -> Self {
+    pub fn new<P: Into<Players>>(name: String, players: P, rules: RuleSet) -> Self {
         let players: Players = players.into();
         Self {
             name,
             scores: vec![Points::new(players.len())],
             players,
             deals: vec![],
+            rules,
+            first_dealer: 0,
+            dealer_changes: vec![],
+            undone: vec![],
+            end: None,
+            finished: false,
+            version: 0,
         }
     }
 
-    pub fn undo_deal(&mut self) -> Option<Deal> {
-        if let Some(deal) = self.deals.pop() {
+    /// The number of rounds before the n'th one in which cards were dealt
+    fn dealt_before(&self, n: usize) -> usize {
+        self.deals[..n.min(self.deals.len())]
+            .iter()
+            .filter(|round| !matches!(round, Round::Correction { .. }))
+            .count()
+    }
+
+    /// The player that deals the n'th round
+    pub fn dealer_at(&self, n: usize) -> usize {
+        let (from, dealer) = self
+            .dealer_changes
+            .iter()
+            .rev()
+            .find(|(round, _)| *round <= n)
+            .copied()
+            .unwrap_or((0, self.first_dealer));
+        (dealer + self.dealt_before(n) - self.dealt_before(from)) % self.players.len()
+    }
+
+    /// The player that deals the next round
+    pub fn dealer(&self) -> usize {
+        self.dealer_at(self.deals.len())
+    }
+
+    /// Makes `player` the dealer of the next round, the rounds that
+    /// were already played keep their dealers
+    pub fn set_dealer(&mut self, player: usize) {
+        let player = player % self.players.len();
+        let next = self.deals.len();
+        self.dealer_changes.retain(|(round, _)| *round < next);
+        if next == 0 {
+            self.first_dealer = player;
+        } else {
+            self.dealer_changes.push((next, player));
+        }
+    }
+
+    /// The four players that play the n'th round, in seating order.
+    ///
+    /// With more than four players the dealer sits out, together with
+    /// everyone that is not among the four players seated after the dealer.
+    pub fn active_at(&self, n: usize) -> Vec<usize> {
+        let num_players = self.players.len();
+        if num_players <= 4 {
+            return (0..num_players).collect();
+        }
+
+        let dealer = self.dealer_at(n);
+        (1..=4).map(|i| (dealer + i) % num_players).collect()
+    }
+
+    /// The players that play the next round
+    pub fn active_players(&self) -> Vec<usize> {
+        self.active_at(self.deals.len())
+    }
+
+    /// Whether the player plays the next round
+    pub fn is_active(&self, player: &usize) -> bool {
+        self.active_players().contains(player)
+    }
+
+    /// Whether the player plays the n'th round
+    pub fn is_active_at(&self, player: &usize, n: &usize) -> bool {
+        self.active_at(*n).contains(player)
+    }
+
+    /// The names of the players that sit out the next round
+    pub fn sitting_out(&self) -> Vec<String> {
+        let active = self.active_players();
+        (0..self.players.len())
+            .filter(|i| !active.contains(i))
+            .map(|i| self.players[i].to_string())
+            .collect()
+    }
+
+    /// Checks that the deal is played by four different players that are
+    /// all at the table for the n'th round
+    fn check_seats(&self, deal: &Deal, n: usize) -> Result<(), Error> {
+        if matches!(&deal.team, Team::Misery(players, _) if players.is_empty()) {
+            return Err(Error::NoMisery);
+        }
+        let seats = deal.team.seats();
+        if seats.len() != 4 {
+            return Err(Error::PlayerNameProblem);
+        }
+        let active = self.active_at(n);
+
+        for (i, seat) in seats.iter().enumerate() {
+            if *seat >= self.players.len() || seats[..i].contains(seat) {
+                return Err(Error::PlayerNameProblem);
+            }
+            if !active.contains(seat) {
+                return Err(Error::SittingOut(self.players[*seat].to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the seats of every deal from the n'th round on, as an earlier
+    /// round changed and can have moved the dealer of the rounds after it
+    fn check_rounds_from(&self, n: usize) -> Result<(), Error> {
+        for (i, round) in self.deals.iter().enumerate().skip(n) {
+            if let Round::Deal(deal) = round {
+                self.check_seats(deal, i)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn undo_deal(&mut self) -> Option<Round> {
+        if self.finished {
+            return None;
+        }
+        if let Some(round) = self.deals.pop() {
             self.scores.pop();
-            Some(deal)
+            let next = self.deals.len();
+            self.dealer_changes.retain(|(round, _)| *round <= next);
+            self.undone.push(round.clone());
+            Some(round)
         } else {
             None
         }
     }
 
-    pub fn add_deal(&mut self, deal: Deal) {
-        self.deals.push(deal.clone());
-        let points = deal.to_points(self.players.len());
-        self.add_points(points);
+    /// Adds the last undone round back to the game
+    pub fn redo_deal(&mut self) -> Option<Round> {
+        if self.finished {
+            return None;
+        }
+        let round = self.undone.pop()?;
+        self.deals.push(round.clone());
+        if self.rebuild_scores(self.deals.len() - 1).is_err() {
+            self.deals.pop();
+            self.undone.push(round);
+            return None;
+        }
+        self.check_end();
+        Some(round)
     }
 
-    pub fn add_points(&mut self, points: Points) {
-        if let Some(last_scores) = self.scores.last() {
-            let new_score = last_scores + &points;
-            self.scores.push(new_score);
+    /// The factor the n'th round is scored with: a round right after
+    /// everybody passed counts double, no matter how often they passed
+    pub fn multiplier_at(&self, n: usize) -> i16 {
+        let passed = self.deals[..n.min(self.deals.len())]
+            .iter()
+            .rev()
+            .find(|round| !matches!(round, Round::Correction { .. }))
+            .is_some_and(|round| matches!(round, Round::AllPass));
+        if passed {
+            2
         } else {
-            self.scores.push(points);
+            1
+        }
+    }
+
+    /// The factor the next round is scored with
+    pub fn multiplier(&self) -> i16 {
+        self.multiplier_at(self.deals.len())
+    }
+
+    pub fn add_deal(&mut self, deal: Deal) -> Result<(), Error> {
+        self.check_open()?;
+        self.check_seats(&deal, self.deals.len())?;
+        self.push_round(Round::Deal(deal))?;
+        self.check_end();
+        Ok(())
+    }
+
+    /// Records a round in which everybody passed
+    pub fn add_all_pass(&mut self) -> Result<(), Error> {
+        self.check_open()?;
+        self.push_round(Round::AllPass)
+    }
+
+    /// Records a manual change of the scores, which has to be zero-sum
+    pub fn add_correction(&mut self, reason: String, points: Points) -> Result<(), Error> {
+        self.check_open()?;
+        Self::check_correction(&points, self.players.len())?;
+        self.push_round(Round::Correction { reason, points })?;
+        self.check_end();
+        Ok(())
+    }
+
+    /// Adds a round at the end, unless the scores get too large with it
+    fn push_round(&mut self, round: Round) -> Result<(), Error> {
+        self.deals.push(round);
+        if let Err(e) = self.rebuild_scores(self.deals.len() - 1) {
+            self.deals.pop();
+            return Err(e);
+        }
+        self.undone.clear();
+        Ok(())
+    }
+
+    fn check_correction(points: &Points, num_players: usize) -> Result<(), Error> {
+        if points.0.iter().any(|x| x.abs() > MAX_CORRECTION) {
+            return Err(Error::CorrectionTooLarge(MAX_CORRECTION));
+        }
+        if points.0.len() != num_players || !points.is_zero_sum() {
+            return Err(Error::NotZeroSum);
+        }
+        Ok(())
+    }
+
+    /// Replaces the n'th round by another deal
+    pub fn replace_deal(&mut self, n: usize, deal: Deal) -> Result<(), Error> {
+        self.replace_round(n, Round::Deal(deal))
+    }
+
+    /// Replaces the n'th round, and recomputes the scores from there on.
+    ///
+    /// The rounds after it have to stay valid with the dealers they get then.
+    pub fn replace_round(&mut self, n: usize, round: Round) -> Result<(), Error> {
+        self.check_open()?;
+        if n >= self.deals.len() {
+            return Err(Error::NoRound);
         }
+        match &round {
+            Round::Deal(deal) => self.check_seats(deal, n)?,
+            Round::Correction { points, .. } => Self::check_correction(points, self.players.len())?,
+            Round::AllPass => {}
+        }
+        let previous = std::mem::replace(&mut self.deals[n], round);
+        if let Err(e) = self
+            .check_rounds_from(n + 1)
+            .and_then(|_| self.rebuild_scores(n))
+        {
+            self.deals[n] = previous;
+            return Err(e);
+        }
+        self.undone.clear();
+        self.check_end();
+        Ok(())
+    }
+
+    /// Removes the n'th round, and recomputes the scores from there on.
+    ///
+    /// The rounds after it have to stay valid with the dealers they get then.
+    pub fn remove_deal(&mut self, n: usize) -> Result<Round, Error> {
+        self.check_open()?;
+        if n >= self.deals.len() {
+            return Err(Error::NoRound);
+        }
+        let dealer_changes = self.dealer_changes.clone();
+        let round = self.deals.remove(n);
+        // a dealer chosen for the round after the removed one wins
+        // over the one chosen for the removed round itself
+        if self.dealer_changes.iter().any(|(round, _)| *round == n + 1) {
+            self.dealer_changes.retain(|(round, _)| *round != n);
+        }
+        for (round, _) in &mut self.dealer_changes {
+            if *round > n {
+                *round -= 1;
+            }
+        }
+        if let Err(e) = self
+            .check_rounds_from(n)
+            .and_then(|_| self.rebuild_scores(n))
+        {
+            self.deals.insert(n, round);
+            self.dealer_changes = dealer_changes;
+            return Err(e);
+        }
+        self.undone.clear();
+        self.check_end();
+        Ok(round)
+    }
+
+    /// The number of deals that were actually played
+    pub fn rounds_played(&self) -> usize {
+        self.deals
+            .iter()
+            .filter(|round| matches!(round, Round::Deal(_)))
+            .count()
+    }
+
+    fn check_open(&self) -> Result<(), Error> {
+        if self.finished {
+            return Err(Error::GameFinished);
+        }
+        Ok(())
+    }
+
+    /// Finishes the game when its end condition is met
+    fn check_end(&mut self) {
+        let ended = match self.end {
+            None => false,
+            Some(EndCondition::Rounds(rounds)) => self.rounds_played() >= rounds,
+            Some(EndCondition::Score(score)) => self.last_score().0.iter().any(|s| *s >= score),
+        };
+        if ended {
+            self.finish();
+        }
+    }
+
+    /// Changes when the game ends, which can end it right away
+    pub fn set_end(&mut self, end: Option<EndCondition>) {
+        self.end = end;
+        self.check_end();
+    }
+
+    /// Ends the game, locking all of its rounds
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.undone.clear();
+    }
+
+    /// Unlocks a finished game, so that rounds can be added again
+    pub fn reopen(&mut self) {
+        self.finished = false;
+    }
+
+    /// Whether the game was saved again since the given version was shown
+    pub fn changed_since(&self, version: Option<i64>) -> bool {
+        version.is_some_and(|version| version != self.version)
+    }
+
+    /// The players and their scores, from first to last place
+    pub fn standings(&self) -> Vec<(String, i16)> {
+        let mut standings: Vec<(String, i16)> = (&self.players)
+            .into_iter()
+            .cloned()
+            .zip(self.last_score().0.iter().copied())
+            .collect();
+        standings.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        standings
+    }
+
+    /// The points that the n'th round adds to the scores
+    fn round_to_points(&self, n: usize) -> Result<Points, Error> {
+        let points = match &self.deals[n] {
+            Round::Deal(deal) => {
+                deal.clone().to_points(self.players.len(), &self.rules)? * self.multiplier_at(n)
+            }
+            Round::AllPass => Points::new(self.players.len()),
+            Round::Correction { points, .. } => points.clone(),
+        };
+        Ok(points)
+    }
+
+    /// Recomputes all cumulative scores from the rounds, as after loading them
+    pub fn recompute_scores(&mut self) -> Result<(), Error> {
+        self.scores = vec![Points::new(self.players.len())];
+        self.rebuild_scores(0)
+    }
+
+    /// Recomputes the cumulative scores of the n'th round and all rounds after it.
+    /// The scores stay as they were when one of them gets too large.
+    fn rebuild_scores(&mut self, n: usize) -> Result<(), Error> {
+        let mut score = self.scores[n].clone();
+        let mut scores = Vec::with_capacity(self.deals.len() - n);
+        for i in n..self.deals.len() {
+            score = score
+                .checked_add(&self.round_to_points(i)?)
+                .ok_or(Error::ScoreOverflow)?;
+            scores.push(score.clone());
+        }
+        self.scores.truncate(n + 1);
+        self.scores.extend(scores);
+        Ok(())
+    }
+
+    /// A short description of the n'th round, for the score history
+    pub fn describe(&self, n: &usize) -> String {
+        match &self.deals[*n] {
+            Round::Deal(deal) => {
+                let description = match &deal.team {
+                    Team::Solo(player, _) => {
+                        format!(
+                            "{}: {} ({})",
+                            &self.players[*player], deal.bid, deal.achieved
+                        )
+                    }
+                    Team::Duo((p1, p2), _) => format!(
+                        "{} & {}: {} ({})",
+                        &self.players[*p1], &self.players[*p2], deal.bid, deal.achieved
+                    ),
+                    Team::Misery(players, _) => {
+                        let outcomes = players
+                            .iter()
+                            .map(|(player, achieved)| {
+                                let outcome = if *achieved == 0 { "✓" } else { "✗" };
+                                format!("{} {outcome}", &self.players[*player])
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        format!("{}: {outcomes}", deal.bid)
+                    }
+                };
+                let multiplier = self.multiplier_at(*n);
+                if multiplier > 1 {
+                    format!("{description} ×{multiplier}")
+                } else {
+                    description
+                }
+            }
+            Round::AllPass => "iedereen past".to_string(),
+            Round::Correction { reason, .. } => format!("correctie: {reason}"),
+        }
+    }
+
+    /// Whether the n'th round is a manual correction
+    pub fn is_correction(&self, n: &usize) -> bool {
+        matches!(self.deals.get(*n), Some(Round::Correction { .. }))
     }
 
     pub fn last_score(&self) -> &Points {
@@ -463,8 +960,197 @@ impl Game {
         }
     }
 
+    /// The points of the n'th round, counting from zero
+    pub fn round_points(&self, n: &usize) -> Points {
+        self.diff(n + 1)
+            .unwrap_or_else(|| Points::new(self.players.len()))
+    }
+
     /// Return the points that were obtained in the last round
     pub fn last_diff(&self) -> Option<Points> {
         self.diff(self.scores.len() - 1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(players: &[&str]) -> Game {
+        Game::new("test".to_string(), players, RuleSet::default())
+    }
+
+    fn solo(player: usize, opps: (usize, usize, usize), bid: &str, achieved: i16) -> Deal {
+        Deal {
+            team: Team::Solo(player, opps),
+            bid: bid.parse().unwrap(),
+            achieved,
+        }
+    }
+
+    #[test]
+    fn bids_parse_back_from_their_names() {
+        let names = solo_bids().into_iter().chain(duo_bids());
+        for name in names {
+            let bid: Bid = name.parse().unwrap();
+            assert_eq!(bid.to_string(), name);
+        }
+        assert!(matches!("  solo SLIM ".parse(), Ok(Bid::GrandSlam)));
+        assert!("Solo 9".parse::<Bid>().is_err());
+    }
+
+    #[test]
+    fn several_passes_count_double_once() {
+        let mut game = game(&["A", "B", "C", "D"]);
+        for _ in 0..12 {
+            game.add_all_pass().unwrap();
+        }
+        assert_eq!(game.multiplier(), 2);
+
+        game.add_deal(solo(0, (1, 2, 3), "Solo Slim", 13)).unwrap();
+        assert_eq!(game.last_score().0, vec![360, -120, -120, -120]);
+        assert_eq!(game.multiplier(), 1);
+    }
+
+    #[test]
+    fn dealer_moves_on_and_sits_out() {
+        let mut game = game(&["A", "B", "C", "D", "E"]);
+        assert_eq!(game.dealer(), 0);
+        assert_eq!(game.active_players(), vec![1, 2, 3, 4]);
+        assert_eq!(game.sitting_out(), vec!["A"]);
+
+        game.add_deal(solo(1, (2, 3, 4), "Solo 6", 6)).unwrap();
+        assert_eq!(game.dealer(), 1);
+        assert_eq!(game.active_players(), vec![2, 3, 4, 0]);
+        assert!(matches!(
+            game.add_deal(solo(1, (2, 3, 4), "Solo 6", 6)),
+            Err(Error::SittingOut(_))
+        ));
+
+        // nobody deals for a correction
+        let nothing = Points(vec![0, 0, 0, 0, 0]);
+        game.add_correction("renonce".to_string(), nothing).unwrap();
+        assert_eq!(game.dealer(), 1);
+        game.add_all_pass().unwrap();
+        assert_eq!(game.dealer(), 2);
+    }
+
+    #[test]
+    fn new_dealer_keeps_the_rounds_played() {
+        let mut game = game(&["A", "B", "C", "D", "E"]);
+        game.add_deal(solo(1, (2, 3, 4), "Solo 6", 6)).unwrap();
+        game.set_dealer(3);
+        assert_eq!(game.dealer_at(0), 0);
+        assert_eq!(game.dealer(), 3);
+
+        game.add_deal(solo(4, (0, 1, 2), "Solo 6", 6)).unwrap();
+        assert_eq!(game.dealer(), 4);
+        let deal = solo(2, (1, 3, 4), "Solo 6", 5);
+        game.replace_deal(0, deal).unwrap();
+
+        game.undo_deal();
+        assert_eq!(game.dealer(), 3);
+        game.set_dealer(0);
+        assert_eq!(game.dealer_at(0), 0);
+        assert_eq!(game.dealer(), 0);
+    }
+
+    #[test]
+    fn edits_keep_later_rounds_valid() {
+        let mut game = game(&["A", "B", "C", "D", "E"]);
+        game.add_deal(solo(1, (2, 3, 4), "Solo 6", 6)).unwrap();
+        game.add_all_pass().unwrap();
+        game.add_deal(solo(0, (1, 3, 4), "Solo 6", 6)).unwrap();
+        game.add_deal(solo(0, (1, 2, 4), "Solo 6", 6)).unwrap();
+        game.undo_deal();
+
+        // without the pass, B would deal the last round and sit it out
+        assert!(matches!(game.remove_deal(1), Err(Error::SittingOut(_))));
+        let nothing = Points(vec![0; 5]);
+        let correction = Round::Correction {
+            reason: "fout".to_string(),
+            points: nothing,
+        };
+        assert!(game.replace_round(1, correction).is_err());
+        assert_eq!(game.deals.len(), 3);
+        assert_eq!(game.dealer_at(2), 2);
+        assert_eq!(game.undone.len(), 1);
+
+        game.remove_deal(2).unwrap();
+        assert!(game.undone.is_empty());
+        game.set_end(Some(EndCondition::Rounds(2)));
+        assert!(!game.finished);
+        let deal = solo(2, (3, 4, 0), "Solo 6", 6);
+        game.replace_deal(1, deal).unwrap();
+        assert!(game.finished);
+    }
+
+    #[test]
+    fn corrections_stay_in_range() {
+        let mut game = game(&["A", "B", "C", "D"]);
+        let huge = Points(vec![30000, 30000, -30000, -30000]);
+        assert!(huge.is_zero_sum());
+        assert!(matches!(
+            game.add_correction("fout".to_string(), huge),
+            Err(Error::CorrectionTooLarge(_))
+        ));
+
+        let most = Points(vec![MAX_CORRECTION, 0, 0, -MAX_CORRECTION]);
+        for _ in 0..32 {
+            game.add_correction("fout".to_string(), most.clone())
+                .unwrap();
+        }
+        assert!(matches!(
+            game.add_correction("fout".to_string(), most),
+            Err(Error::ScoreOverflow)
+        ));
+        assert_eq!(game.deals.len(), 32);
+        assert_eq!(game.last_score().0, vec![32000, 0, 0, -32000]);
+    }
+
+    #[test]
+    fn misery_is_played_by_four() {
+        let mut game = game(&["A", "B", "C", "D", "E"]);
+        let misery = |team, opps| Deal {
+            team: Team::Misery(team, opps),
+            bid: Bid::SmallMisery,
+            achieved: 0,
+        };
+        assert!(matches!(
+            game.add_deal(misery(vec![(1, 0)], vec![2, 3])),
+            Err(Error::PlayerNameProblem)
+        ));
+        assert!(matches!(
+            game.add_deal(misery(vec![], vec![1, 2, 3, 4])),
+            Err(Error::NoMisery)
+        ));
+
+        // every misery is settled on its own against the three others
+        game.add_deal(misery(vec![(1, 0), (2, 1)], vec![3, 4]))
+            .unwrap();
+        assert_eq!(game.last_score().0, vec![0, 24, -24, 0, 0]);
+        assert!(game.last_score().is_zero_sum());
+    }
+
+    #[test]
+    fn unknown_bid_is_refused() {
+        let mut game = game(&["A", "B", "C", "D"]);
+        let deal = Deal {
+            team: Team::Solo(0, (1, 2, 3)),
+            bid: Bid::Solo(9),
+            achieved: 9,
+        };
+        assert!(matches!(game.add_deal(deal), Err(Error::UnknownBid(_))));
+        assert!(game.deals.is_empty());
+        assert_eq!(game.scores.len(), 1);
+    }
+
+    #[test]
+    fn correction_keeps_the_passes() {
+        let mut game = game(&["A", "B", "C", "D"]);
+        game.add_all_pass().unwrap();
+        let penalty = Points(vec![3, -1, -1, -1]);
+        game.add_correction("renonce".to_string(), penalty).unwrap();
+        assert_eq!(game.multiplier(), 2);
+    }
+}